@@ -0,0 +1,161 @@
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+use std::env;
+use std::sync::Mutex;
+
+use crate::log_msg;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls flow normally.
+    Closed,
+    /// JobNimbus looks down; deliveries are queued without calling it.
+    Open,
+    /// The cool-down is over and a single probe call is allowed through.
+    HalfOpen,
+}
+
+struct Breaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<DateTime<Local>>,
+    probe_started_at: Option<DateTime<Local>>,
+}
+
+static BREAKER: Mutex<Breaker> = Mutex::new(Breaker {
+    state: CircuitState::Closed,
+    consecutive_failures: 0,
+    opened_at: None,
+    probe_started_at: None,
+});
+
+/// Breaker state for `/health`, the dashboard and `/metrics`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CircuitStatus {
+    pub enabled: bool,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub failure_threshold: u32,
+    pub opened_at: Option<DateTime<Local>>,
+    /// When the next probe is allowed while open.
+    pub retry_at: Option<DateTime<Local>>,
+}
+
+/// Consecutive failures that open the circuit (`CIRCUIT_FAILURE_THRESHOLD`, default 5, 0 disables).
+fn failure_threshold() -> u32 {
+    env::var("CIRCUIT_FAILURE_THRESHOLD")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(5)
+}
+
+/// How long the circuit stays open before probing (`CIRCUIT_OPEN_SECS`, default 30).
+fn open_for() -> Duration {
+    let secs = env::var("CIRCUIT_OPEN_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(30);
+    Duration::seconds(secs)
+}
+
+pub fn enabled() -> bool {
+    failure_threshold() > 0
+}
+
+/// Whether a call to JobNimbus may go ahead. Once the open period is over the
+/// first caller becomes the half-open probe; everyone else keeps waiting
+/// until the probe reports back (or takes longer than an open period).
+pub fn allow() -> bool {
+    if !enabled() {
+        return true;
+    }
+    let mut breaker = BREAKER.lock().unwrap();
+    let now = Local::now();
+    match breaker.state {
+        CircuitState::Closed => true,
+        CircuitState::Open => {
+            if breaker.opened_at.is_some_and(|at| now - at < open_for()) {
+                return false;
+            }
+            breaker.state = CircuitState::HalfOpen;
+            breaker.probe_started_at = Some(now);
+            log_msg("Circuit breaker half-open: probing Job Nimbus", "🔌");
+            true
+        }
+        CircuitState::HalfOpen => {
+            if breaker.probe_started_at.is_some_and(|at| now - at < open_for()) {
+                return false;
+            }
+            breaker.probe_started_at = Some(now);
+            true
+        }
+    }
+}
+
+/// Time left until the next probe, for rescheduling deliveries while open.
+pub fn retry_in() -> std::time::Duration {
+    let breaker = BREAKER.lock().unwrap();
+    let since = breaker.probe_started_at.or(breaker.opened_at).unwrap_or_else(Local::now);
+    (since + open_for() - Local::now()).to_std().unwrap_or_default()
+}
+
+/// JobNimbus answered; any answer below 500 means it is up.
+pub fn record_success() {
+    let mut breaker = BREAKER.lock().unwrap();
+    if breaker.state != CircuitState::Closed {
+        log_msg("Circuit breaker closed: Job Nimbus is answering again", "🔌");
+    }
+    breaker.state = CircuitState::Closed;
+    breaker.consecutive_failures = 0;
+    breaker.opened_at = None;
+    breaker.probe_started_at = None;
+}
+
+/// A connection failure or 5xx answer.
+pub fn record_failure() {
+    if !enabled() {
+        return;
+    }
+    let threshold = failure_threshold();
+    let mut breaker = BREAKER.lock().unwrap();
+    breaker.consecutive_failures += 1;
+    let reopen = match breaker.state {
+        CircuitState::Closed => breaker.consecutive_failures >= threshold,
+        CircuitState::HalfOpen => true,
+        CircuitState::Open => false,
+    };
+    if reopen {
+        let was_probing = breaker.state == CircuitState::HalfOpen;
+        breaker.state = CircuitState::Open;
+        breaker.opened_at = Some(Local::now());
+        breaker.probe_started_at = None;
+        log_msg(
+            &if was_probing {
+                format!("Circuit breaker re-opened: probe failed, next probe in {}s", open_for().num_seconds())
+            } else {
+                format!(
+                    "Circuit breaker opened after {} consecutive failures; queueing deliveries for {}s",
+                    breaker.consecutive_failures,
+                    open_for().num_seconds()
+                )
+            },
+            "🔌",
+        );
+    }
+}
+
+pub fn status() -> CircuitStatus {
+    let breaker = BREAKER.lock().unwrap();
+    CircuitStatus {
+        enabled: enabled(),
+        state: breaker.state,
+        consecutive_failures: breaker.consecutive_failures,
+        failure_threshold: failure_threshold(),
+        opened_at: breaker.opened_at,
+        retry_at: match breaker.state {
+            CircuitState::Closed => None,
+            _ => breaker.probe_started_at.or(breaker.opened_at).map(|at| at + open_for()),
+        },
+    }
+}
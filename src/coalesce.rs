@@ -0,0 +1,123 @@
+use chrono::Duration;
+use serde_json::Value;
+use std::env;
+use std::io;
+use std::sync::Mutex;
+
+use crate::idmap;
+use crate::log_msg;
+use crate::ordering::record_key;
+use crate::queue::{self, Delivery};
+
+/// How long the first update to a record is held so later ones can be merged
+/// into it (`COALESCE_WINDOW_SECS`, default 0 = off).
+pub fn window() -> Option<Duration> {
+    env::var("COALESCE_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::seconds)
+}
+
+/// The record updates are merged by. Unlike per-record ordering this needs
+/// the SCH record id: payloads identified only by email aren't merged.
+pub fn merge_key(delivery: &Delivery) -> Option<String> {
+    idmap::sch_record_id(&delivery.payload)?;
+    record_key(delivery)
+}
+
+/// Folds `update` into a queued, not yet attempted delivery for the same
+/// record that was routed the same way, or queues it as the one later updates are merged into. Returns the
+/// delivery it was merged into, if there was one. Both happen under one lock,
+/// so two first updates arriving together can't both be queued.
+pub fn merge_or_enqueue(update: &Delivery) -> io::Result<Option<Delivery>> {
+    static MERGING: Mutex<()> = Mutex::new(());
+    let _merging = MERGING.lock().unwrap();
+    if let Some(merged) = merge_into_pending(update) {
+        return Ok(Some(merged));
+    }
+    queue::persist(update)?;
+    Ok(None)
+}
+
+fn merge_into_pending(update: &Delivery) -> Option<Delivery> {
+    let key = merge_key(update)?;
+    let candidates = queue::pending_for(&key)
+        .into_iter()
+        .rev()
+        .filter(|(_, attempts)| *attempts == 0);
+
+    for (id, _) in candidates {
+        if !queue::claim(&id) {
+            continue;
+        }
+        // Re-read under the claim in case it was sent in the meantime. A
+        // delivery routed by another rule keeps to itself: merging would send
+        // the update by rules that weren't picked for it.
+        let Some(mut pending) = queue::get(&id).filter(|p| p.attempts == 0 && same_routing(p, update)) else {
+            queue::release(&id);
+            continue;
+        };
+
+        merge(&mut pending, update);
+        let stored = queue::persist(&pending);
+        queue::release(&pending.id);
+        return match stored {
+            Ok(_) => {
+                log_msg(
+                    &format!(
+                        "Delivery {} merged into pending delivery {} for {} ({} updates)",
+                        update.id,
+                        pending.id,
+                        key,
+                        pending.merged.len() + 1
+                    ),
+                    "🧬",
+                );
+                Some(pending)
+            }
+            Err(e) => {
+                log_msg(&format!("Failed to merge delivery {} into {}: {}", update.id, pending.id, e), "❌");
+                None
+            }
+        };
+    }
+    None
+}
+
+fn same_routing(pending: &Delivery, update: &Delivery) -> bool {
+    pending.target == update.target && pending.rule == update.rule && pending.tags == update.tags
+}
+
+fn merge(into: &mut Delivery, update: &Delivery) {
+    let mut body: Value = serde_json::from_str(&into.body).unwrap_or(Value::Null);
+    deep_merge(&mut body, serde_json::from_str(&update.body).unwrap_or(Value::Null));
+    into.body = serde_json::to_string_pretty(&body).unwrap_or_else(|_| update.body.clone());
+
+    match (&mut into.payload.data, &update.payload.data) {
+        (Some(data), Some(newer)) => deep_merge(data, newer.clone()),
+        (data @ None, Some(newer)) => *data = Some(newer.clone()),
+        _ => {}
+    }
+    into.payload._extra.extend(update.payload._extra.clone());
+    into.plugins.extend(update.plugins.iter().cloned());
+    into.merged.push(update.id.clone());
+}
+
+/// Overlays `update` on `base`: objects merge key by key, anything else is
+/// replaced by the newer value.
+pub fn deep_merge(base: &mut Value, update: Value) {
+    match (base, update) {
+        (Value::Object(base), Value::Object(update)) => {
+            for (key, value) in update {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, update) => *base = update,
+    }
+}
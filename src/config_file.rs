@@ -0,0 +1,92 @@
+use serde::de::DeserializeOwned;
+use std::env;
+use std::fs::{metadata, read_to_string};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::log_msg;
+
+struct Cached<T> {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    config: Arc<T>,
+}
+
+/// A JSON config file whose location comes from an environment variable,
+/// re-read whenever it changes on disk. A missing file gives the default
+/// config; one that fails to parse or validate is logged and the last good
+/// config kept.
+pub struct Reloadable<T> {
+    /// What the file holds, for log messages, e.g. "routing rules".
+    what: &'static str,
+    env_var: &'static str,
+    default_path: &'static str,
+    validate: fn(&T) -> Result<(), String>,
+    cache: Mutex<Option<Cached<T>>>,
+}
+
+impl<T: DeserializeOwned + Default> Reloadable<T> {
+    pub const fn new(what: &'static str, env_var: &'static str, default_path: &'static str) -> Self {
+        Reloadable { what, env_var, default_path, validate: |_| Ok(()), cache: Mutex::new(None) }
+    }
+
+    /// Checks a freshly parsed config before it replaces the current one.
+    pub const fn validated_by(mut self, validate: fn(&T) -> Result<(), String>) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Location of the file: the environment variable, or the default path.
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(env::var(self.env_var).unwrap_or_else(|_| self.default_path.to_string()))
+    }
+
+    /// The current config, loading it again if the file changed.
+    pub fn current(&self) -> Arc<T> {
+        let path = self.path();
+        let modified = metadata(&path).and_then(|m| m.modified()).ok();
+        let mut cache = self.cache.lock().unwrap();
+
+        if let Some(cached) = cache.as_ref() {
+            if cached.path == path && cached.modified == modified {
+                return cached.config.clone();
+            }
+        }
+
+        let config = match self.load(&path) {
+            Ok(config) => Arc::new(config),
+            Err(e) => {
+                log_msg(&format!("Failed to load {} from {}: {}", self.what, path.display(), e), "❌");
+                cache.as_ref()
+                    .filter(|cached| cached.path == path)
+                    .map(|cached| cached.config.clone())
+                    .unwrap_or_default()
+            }
+        };
+        *cache = Some(Cached { path, modified, config: config.clone() });
+        config
+    }
+
+    /// Forces a reload, reporting errors to the caller instead of keeping the
+    /// last good config.
+    pub fn reload(&self) -> Result<Arc<T>, String> {
+        let path = self.path();
+        let modified = metadata(&path).and_then(|m| m.modified()).ok();
+        let config = Arc::new(self.load(&path)?);
+        *self.cache.lock().unwrap() = Some(Cached { path, modified, config: config.clone() });
+        Ok(config)
+    }
+
+    fn load(&self, path: &Path) -> Result<T, String> {
+        match read_to_string(path) {
+            Ok(content) => {
+                let config: T = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+                (self.validate)(&config)?;
+                Ok(config)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use reqwest::{Client, Method};
+use serde_json::Value;
+use std::env;
+
+use crate::idmap;
+use crate::jobnimbus::{self, UpstreamResponse};
+use crate::log_msg;
+use crate::queue::Delivery;
+
+/// Ways of recognising a contact that already exists in JobNimbus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKey {
+    /// Our stored SCH id -> jnid mapping.
+    ExternalId,
+    Email,
+    Phone,
+}
+
+impl MatchKey {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "external_id" => Some(MatchKey::ExternalId),
+            "email" => Some(MatchKey::Email),
+            "phone" => Some(MatchKey::Phone),
+            _ => None,
+        }
+    }
+}
+
+const PHONE_FIELDS: [&str; 3] = ["mobile_phone", "home_phone", "work_phone"];
+
+enum Lookup {
+    Found(String),
+    NotFound,
+    /// JobNimbus answered the search with an error; pass it on as the result.
+    Failed(UpstreamResponse),
+}
+
+pub fn upsert_enabled() -> bool {
+    env::var("UPSERT_MODE").unwrap_or_else(|_| "false".into()) == "true"
+}
+
+/// Match keys in precedence order (`CONTACT_MATCH_KEYS`, default `external_id,email,phone`).
+pub fn match_keys() -> Vec<MatchKey> {
+    let configured = env::var("CONTACT_MATCH_KEYS").unwrap_or_else(|_| "external_id,email,phone".to_string());
+    let mut keys = Vec::new();
+    for name in configured.split(',').filter(|n| !n.trim().is_empty()) {
+        match MatchKey::parse(name) {
+            Some(key) if !keys.contains(&key) => keys.push(key),
+            Some(_) => {}
+            None => log_msg(&format!("Ignoring unknown contact match key '{}'", name.trim()), "⚠️"),
+        }
+    }
+    keys
+}
+
+/// Updates the matching JobNimbus contact, or creates one if none matches.
+pub async fn upsert(client: &Client, delivery: &Delivery) -> Result<UpstreamResponse, String> {
+    let contact: Value = serde_json::from_str(&delivery.body).unwrap_or(Value::Null);
+    let sch_id = idmap::sch_record_id(&delivery.payload);
+
+    for key in match_keys() {
+        let jnid = match find(client, key, &contact, sch_id.as_deref()).await? {
+            Lookup::Found(jnid) => jnid,
+            Lookup::NotFound => continue,
+            Lookup::Failed(response) => return Ok(response),
+        };
+
+        log_msg(&format!("Delivery {} matched contact {} by {:?}; updating", delivery.id, jnid, key), "🔗");
+        let response = jobnimbus::send(client, Method::PUT, &format!("/contacts/{}", jnid), Some(&delivery.body)).await?;
+
+        // A mapping can outlive the contact; forget it and keep looking.
+        if response.status == 404 && key == MatchKey::ExternalId {
+            if let Some(sch_id) = &sch_id {
+                log_msg(&format!("Mapped contact {} no longer exists; dropping mapping for {}", jnid, sch_id), "⚠️");
+                idmap::forget(sch_id);
+            }
+            continue;
+        }
+        if response.is_success() {
+            if let Some(sch_id) = &sch_id {
+                idmap::record(sch_id, &jnid, Some(&delivery.id));
+            }
+        }
+        return Ok(response);
+    }
+
+    log_msg(&format!("Delivery {} matched no existing contact; creating", delivery.id), "🆕");
+    create(client, delivery).await
+}
+
+/// Creates a new contact and remembers which SCH record it belongs to.
+pub async fn create(client: &Client, delivery: &Delivery) -> Result<UpstreamResponse, String> {
+    let response = jobnimbus::send(client, Method::POST, "/contacts", Some(&delivery.body)).await?;
+    if response.is_success() {
+        if let (Some(sch_id), Some(jnid)) = (idmap::sch_record_id(&delivery.payload), response.jnid()) {
+            idmap::record(&sch_id, &jnid, Some(&delivery.id));
+        }
+    }
+    Ok(response)
+}
+
+async fn find(client: &Client, key: MatchKey, contact: &Value, sch_id: Option<&str>) -> Result<Lookup, String> {
+    let filter = match key {
+        MatchKey::ExternalId => {
+            return Ok(sch_id
+                .and_then(idmap::lookup_jnid)
+                .map_or(Lookup::NotFound, Lookup::Found));
+        }
+        MatchKey::Email => match non_empty_str(contact, "email") {
+            Some(email) => serde_json::json!({ "must": [{ "term": { "email": email } }] }),
+            None => return Ok(Lookup::NotFound),
+        },
+        MatchKey::Phone => {
+            let phones: Vec<&str> = PHONE_FIELDS.iter().filter_map(|f| non_empty_str(contact, f)).collect();
+            if phones.is_empty() {
+                return Ok(Lookup::NotFound);
+            }
+            let mut should = Vec::new();
+            for field in PHONE_FIELDS {
+                for phone in &phones {
+                    let mut term = serde_json::Map::new();
+                    term.insert(field.to_string(), Value::from(*phone));
+                    should.push(serde_json::json!({ "term": term }));
+                }
+            }
+            serde_json::json!({ "must": [{ "bool": { "should": should } }] })
+        }
+    };
+
+    let response = jobnimbus::search_contacts(client, &filter).await?;
+    if !response.is_success() {
+        return Ok(Lookup::Failed(response));
+    }
+    let results: Value = serde_json::from_str(&response.body).unwrap_or(Value::Null);
+    let jnid = results.get("results")
+        .and_then(|r| r.as_array())
+        .and_then(|r| r.first())
+        .and_then(|c| c.get("jnid"))
+        .and_then(|j| j.as_str());
+    Ok(jnid.map_or(Lookup::NotFound, |j| Lookup::Found(j.to_string())))
+}
+
+fn non_empty_str<'a>(value: &'a Value, field: &str) -> Option<&'a str> {
+    value.get(field)?.as_str().map(str::trim).filter(|s| !s.is_empty())
+}
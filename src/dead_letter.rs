@@ -0,0 +1,113 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+use crate::handlers::Payload;
+use crate::jobnimbus::UpstreamResponse;
+use crate::log_msg;
+use crate::queue::{self, Delivery};
+use crate::routing::Target;
+use crate::sink::{SinkResult, SinkState};
+use crate::store::{self, data_dir};
+
+/// A delivery that permanently failed, kept for inspection and replay.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter {
+    pub id: String,
+    pub received_at: DateTime<Local>,
+    pub failed_at: DateTime<Local>,
+    pub reason: String,
+    pub attempts: u32,
+    pub payload: Payload,
+    pub body: String,
+    #[serde(default)]
+    pub target: Target,
+    pub upstream_status: Option<u16>,
+    pub upstream_body: Option<String>,
+    /// Per-sink results; sinks that already succeeded are skipped on replay.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sinks: BTreeMap<String, SinkResult>,
+    /// Stopped before its body was built (a script or plugin failed), so a
+    /// replay runs the payload through the pipeline again.
+    #[serde(default)]
+    pub unprocessed: bool,
+}
+
+fn dead_letter_dir() -> PathBuf {
+    data_dir().join("dead_letters")
+}
+
+/// Parks a failed delivery in the dead-letter store.
+pub fn park(delivery: &Delivery, reason: &str, response: Option<&UpstreamResponse>) {
+    keep(letter(delivery, reason, response));
+}
+
+/// Parks a payload that failed before it had a body to send.
+pub fn park_unprocessed(delivery: &Delivery, reason: &str) {
+    keep(DeadLetter { unprocessed: true, ..letter(delivery, reason, None) });
+}
+
+fn letter(delivery: &Delivery, reason: &str, response: Option<&UpstreamResponse>) -> DeadLetter {
+    DeadLetter {
+        id: delivery.id.clone(),
+        received_at: delivery.received_at,
+        failed_at: Local::now(),
+        reason: reason.to_string(),
+        attempts: delivery.attempts,
+        payload: delivery.payload.clone(),
+        body: delivery.body.clone(),
+        target: delivery.target,
+        upstream_status: response.map(|r| r.status),
+        upstream_body: response.map(|r| r.body.clone()),
+        sinks: delivery.sinks.clone(),
+        unprocessed: false,
+    }
+}
+
+fn keep(letter: DeadLetter) {
+    match save(&letter) {
+        Ok(_) => log_msg(&format!("Delivery {} moved to dead letters: {}", letter.id, letter.reason), "📮"),
+        Err(e) => log_msg(&format!("Failed to store dead letter {}: {}", letter.id, e), "❌"),
+    }
+}
+
+pub fn save(letter: &DeadLetter) -> io::Result<()> {
+    store::write_json(&dead_letter_dir(), &letter.id, letter)
+}
+
+pub fn get(id: &str) -> Option<DeadLetter> {
+    if !store::is_valid_id(id) {
+        return None;
+    }
+    store::read_json(&dead_letter_dir(), id)
+}
+
+/// All dead letters, oldest first.
+pub fn list() -> Vec<DeadLetter> {
+    store::list_json(&dead_letter_dir())
+}
+
+pub fn discard(id: &str) -> bool {
+    store::is_valid_id(id) && store::remove_json(&dead_letter_dir(), id)
+}
+
+/// Puts a dead letter back on the delivery queue with a fresh retry budget.
+pub fn replay(id: &str) -> io::Result<Option<Delivery>> {
+    let letter = match get(id) {
+        Some(letter) => letter,
+        None => return Ok(None),
+    };
+
+    let mut delivery = Delivery::new(letter.payload, letter.body);
+    delivery.id = letter.id;
+    delivery.received_at = letter.received_at;
+    delivery.target = letter.target;
+    delivery.sinks = letter.sinks;
+    delivery.sinks.retain(|_, result| result.status == SinkState::Succeeded);
+    queue::persist(&delivery)?;
+    store::remove_json(&dead_letter_dir(), id);
+    log_msg(&format!("Dead letter {} re-submitted to the delivery queue", id), "🔄");
+    Ok(Some(delivery))
+}
@@ -0,0 +1,246 @@
+use chrono::Local;
+use reqwest::Client;
+
+use crate::circuit_breaker;
+use crate::dead_letter;
+use crate::history::{self, DeliveryState};
+use crate::jobnimbus::UpstreamResponse;
+use crate::log_msg;
+use crate::metrics;
+use crate::ordering::{self, StalePolicy};
+use crate::queue::{self, Delivery};
+use crate::rate_limit;
+use crate::retry::{is_retryable_status, RetryPolicy};
+use crate::sink::{self, Sink, SinkResult, SinkState};
+
+/// Result of a single delivery attempt.
+pub enum Outcome {
+    /// Every sink accepted the delivery (carrying the first answer); it has
+    /// left the queue.
+    Delivered(UpstreamResponse),
+    /// A sink failed transiently and the delivery was rescheduled for the
+    /// sinks that don't have it yet.
+    Retrying { error: String },
+    /// A sink rejected the delivery or retries are exhausted; it has been
+    /// moved from the queue to the dead-letter store.
+    Failed { error: String, response: Option<UpstreamResponse> },
+    /// The event is older than one already delivered for the same record and
+    /// `STALE_EVENT_POLICY=drop`; it has left the queue unsent.
+    Dropped { reason: String },
+}
+
+/// Makes one attempt at a claimed delivery and applies the retry policy to the
+/// result. The claim is always released before returning.
+pub async fn attempt(client: &Client, delivery: &mut Delivery) -> Outcome {
+    let policy = RetryPolicy::from_env();
+
+    // Writes to one record go out one at a time and in arrival order, while
+    // other records carry on in parallel.
+    let record_key = ordering::record_key(delivery);
+    let _record_lock = match &record_key {
+        Some(key) => Some(ordering::lock(key).await),
+        None => None,
+    };
+    let event_time = ordering::event_time(&delivery.payload);
+    if let Some(key) = &record_key {
+        if let Some(earlier) = ordering::earlier_pending(delivery, key) {
+            log_msg(
+                &format!("Delivery {} waiting for earlier delivery {} to {}", delivery.id, earlier.id, key),
+                "🚦",
+            );
+            let wait = earlier.next_attempt_at
+                .and_then(|at| (at - Local::now()).to_std().ok())
+                .unwrap_or_default()
+                .max(std::time::Duration::from_secs(1));
+            return defer(delivery, wait, &format!("Waiting for earlier delivery {}", earlier.id));
+        }
+        if let Some(mark) = event_time.and_then(|time| ordering::newer_delivered(key, time)) {
+            metrics::incr("sch2jn_stale_events_total");
+            let reason = format!(
+                "Stale event for {}: delivery {} already sent a newer update ({})",
+                key, mark.delivery_id, mark.event_time.to_rfc3339()
+            );
+            delivery.stale = true;
+            if ordering::stale_policy() == StalePolicy::Drop {
+                log_msg(&format!("Delivery {} dropped. {}", delivery.id, reason), "🕰️");
+                queue::complete(&delivery.id);
+                history::record(delivery, DeliveryState::Dropped, None, Some(&reason));
+                return Outcome::Dropped { reason };
+            }
+            log_msg(&format!("Delivery {} flagged. {}", delivery.id, reason), "🕰️");
+        }
+    }
+
+    // Sinks that succeeded or gave up on an earlier attempt are left alone.
+    let sinks: Vec<Box<dyn Sink>> = sink::configured()
+        .into_iter()
+        .filter(|sink| !delivery.sinks.get(sink.name()).is_some_and(SinkResult::is_done))
+        .collect();
+
+    if sinks.iter().any(|sink| sink.uses_jobnimbus()) {
+        // Past a short wait for the rate limiter the delivery goes back to the
+        // queue untouched; it hasn't been tried, so it doesn't use up an attempt.
+        let wait = rate_limit::delay();
+        if wait > rate_limit::max_wait() {
+            log_msg(
+                &format!(
+                    "Delivery {} deferred: Job Nimbus rate limit reached, next slot in {:.1}s",
+                    delivery.id,
+                    wait.as_secs_f64()
+                ),
+                "🪣",
+            );
+            metrics::incr("sch2jn_rate_limited_total");
+            return defer(delivery, wait, "Job Nimbus rate limit reached");
+        }
+        // Likewise while the circuit breaker says JobNimbus is down.
+        if !circuit_breaker::allow() {
+            let wait = circuit_breaker::retry_in();
+            log_msg(
+                &format!(
+                    "Delivery {} queued: circuit breaker open, next probe in {:.0}s",
+                    delivery.id,
+                    wait.as_secs_f64()
+                ),
+                "🔌",
+            );
+            metrics::incr("sch2jn_circuit_short_circuits_total");
+            return defer(delivery, wait, "Job Nimbus circuit breaker open");
+        }
+    }
+
+    delivery.attempts += 1;
+
+    // Every remaining sink gets this attempt; the first answer that counts as
+    // a success (or failure) is what the sender is told about.
+    let mut delivered = None;
+    let mut rejected = None;
+    let mut retryable = Vec::new();
+    for sink in &sinks {
+        let result = sink.send(client, delivery).await;
+        if sink.uses_jobnimbus() {
+            match &result {
+                Ok(response) if response.limited => {}
+                Ok(response) if response.status < 500 => circuit_breaker::record_success(),
+                _ => circuit_breaker::record_failure(),
+            }
+        }
+
+        let entry = delivery.sinks.entry(sink.name().to_string()).or_default();
+        entry.attempts += 1;
+        match result {
+            Ok(response) if response.status < 400 => {
+                log_msg(
+                    &format!(
+                        "Delivery {} answered by {} on attempt {}/{} (HTTP {})",
+                        delivery.id, sink.label(), delivery.attempts, policy.max_attempts, response.status
+                    ),
+                    "📬",
+                );
+                entry.update(SinkState::Succeeded, Some(&response), None);
+                delivered.get_or_insert(response);
+            }
+            Ok(response) if !is_retryable_status(response.status) => {
+                let error = format!("{} rejected the delivery (HTTP {})", sink.label(), response.status);
+                log_msg(&format!("Delivery {}: {}", delivery.id, error), "❌");
+                entry.update(SinkState::Failed, Some(&response), Some(&error));
+                rejected.get_or_insert((error, response));
+            }
+            Ok(response) => {
+                let error = format!("{} answered HTTP {}", sink.label(), response.status);
+                entry.update(SinkState::Pending, Some(&response), Some(&error));
+                retryable.push((error, Some(response)));
+            }
+            Err(e) => {
+                let error = if sinks.len() > 1 { format!("{}: {}", sink.label(), e) } else { e };
+                entry.update(SinkState::Pending, None, Some(&error));
+                retryable.push((error, None));
+            }
+        }
+    }
+
+    if retryable.is_empty() {
+        // A sink that failed on an earlier attempt still fails the delivery.
+        let failed: Vec<String> = delivery.sinks.values()
+            .filter(|result| result.status == SinkState::Failed)
+            .filter_map(|result| result.error.clone())
+            .collect();
+        if failed.is_empty() {
+            queue::complete(&delivery.id);
+            history::record(delivery, DeliveryState::Succeeded, delivered.as_ref(), None);
+            if let (Some(key), Some(time)) = (&record_key, event_time) {
+                ordering::record_delivered(key, time, &delivery.id);
+            }
+            metrics::incr("sch2jn_deliveries_succeeded_total");
+            // Nothing was sent only if every sink already had it (say, after a
+            // crash between sending and completing).
+            return Outcome::Delivered(delivered.unwrap_or(UpstreamResponse { status: 200, body: String::new(), retry_after: None, limited: false }));
+        }
+        let error = failed.join("; ");
+        let response = rejected.map(|(_, response)| response);
+        dead_letter::park(delivery, &error, response.as_ref());
+        queue::complete(&delivery.id);
+        history::record(delivery, DeliveryState::Failed, response.as_ref(), Some(&error));
+        metrics::incr("sch2jn_deliveries_failed_total");
+        return Outcome::Failed { error, response };
+    }
+
+    let error = retryable.iter().map(|(error, _)| error.as_str()).collect::<Vec<_>>().join("; ");
+    let retry_after = retryable.iter().filter_map(|(_, r)| r.as_ref().and_then(|r| r.retry_after)).max();
+    let response = rejected.map(|(_, response)| response)
+        .or_else(|| retryable.into_iter().find_map(|(_, response)| response));
+
+    if delivery.attempts >= policy.max_attempts {
+        log_msg(
+            &format!(
+                "Delivery {} failed after {} attempts, giving up: {}",
+                delivery.id, delivery.attempts, error
+            ),
+            "❌",
+        );
+        let reason = format!("Retries exhausted after {} attempts: {}", delivery.attempts, error);
+        for result in delivery.sinks.values_mut().filter(|result| !result.is_done()) {
+            result.status = SinkState::Failed;
+        }
+        dead_letter::park(delivery, &reason, response.as_ref());
+        queue::complete(&delivery.id);
+        history::record(delivery, DeliveryState::Failed, response.as_ref(), Some(&reason));
+        metrics::incr("sch2jn_deliveries_failed_total");
+        return Outcome::Failed { error, response };
+    }
+
+    let delay = policy.next_delay(delivery.attempts, retry_after);
+    log_msg(
+        &format!(
+            "Delivery {} attempt {}/{} failed: {}. Retrying in {:.1}s{}",
+            delivery.id,
+            delivery.attempts,
+            policy.max_attempts,
+            error,
+            delay.as_secs_f64(),
+            if retry_after.is_some() { " (Retry-After)" } else { "" }
+        ),
+        "🔄",
+    );
+
+    delivery.last_error = Some(error.clone());
+    delivery.next_attempt_at = chrono::Duration::from_std(delay)
+        .ok()
+        .map(|delay| Local::now() + delay);
+    if let Err(e) = queue::persist(delivery) {
+        log_msg(&format!("Failed to update queued delivery {}: {}", delivery.id, e), "❌");
+    }
+    queue::release(&delivery.id);
+    metrics::incr("sch2jn_delivery_retries_total");
+    Outcome::Retrying { error }
+}
+
+/// Puts a delivery back on the queue without trying it.
+fn defer(delivery: &mut Delivery, wait: std::time::Duration, reason: &str) -> Outcome {
+    delivery.next_attempt_at = chrono::Duration::from_std(wait).ok().map(|wait| Local::now() + wait);
+    if let Err(e) = queue::persist(delivery) {
+        log_msg(&format!("Failed to update queued delivery {}: {}", delivery.id, e), "❌");
+    }
+    queue::release(&delivery.id);
+    Outcome::Retrying { error: reason.to_string() }
+}
@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config_file::Reloadable;
+use crate::handlers::Payload;
+use crate::log_msg;
+use crate::routing::{event_name, glob_match};
+use crate::transform::TransformSpec;
+
+/// The contents of the mapping file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MappingConfig {
+    #[serde(default)]
+    pub mappings: Vec<EventMapping>,
+}
+
+/// How to build the outbound object for events matching `event`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventMapping {
+    /// Event name glob; the first mapping that matches wins.
+    #[serde(default)]
+    pub event: String,
+    /// Lets routing rules pick this mapping regardless of the event name. A
+    /// named mapping without an `event` is only used by rules.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Start from the inbound `data` and overlay the mapped fields.
+    #[serde(default)]
+    pub passthrough: bool,
+    /// Outbound field (dotted for nested objects) -> where its value comes from.
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldSource>,
+    /// Script in `SCRIPTS_DIR` that gets the final say over the mapped object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+}
+
+/// A bare string is shorthand for `{ "path": "..." }`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum FieldSource {
+    Path(String),
+    Spec(FieldSpec),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FieldSpec {
+    /// Inbound path such as `customer.phones[0].number` or `tags[*].name`.
+    /// Paths are relative to `data`; start with `$.` to read the whole payload.
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default, rename = "const")]
+    pub constant: Option<Value>,
+    /// Used when `path` resolves to nothing.
+    #[serde(default)]
+    pub default: Option<Value>,
+    /// Fail the request instead of leaving the field out.
+    #[serde(default)]
+    pub required: bool,
+    /// Applied in order to the resolved value (after `default`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<TransformSpec>,
+}
+
+/// A field that couldn't be mapped.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub error: String,
+}
+
+/// The mapping file (`MAPPINGS_FILE`, default `config/mappings.json`). No
+/// mapping file simply means payloads are forwarded verbatim.
+static MAPPINGS: Reloadable<MappingConfig> =
+    Reloadable::new("field mappings", "MAPPINGS_FILE", "config/mappings.json").validated_by(validate);
+
+/// The current mapping config, re-read whenever the file changes on disk.
+pub fn current() -> Arc<MappingConfig> {
+    MAPPINGS.current()
+}
+
+/// Forces a reload, reporting errors to the caller.
+pub fn reload() -> Result<Arc<MappingConfig>, String> {
+    let config = MAPPINGS.reload()?;
+    log_msg(&format!("Field mappings reloaded ({} event mappings)", config.mappings.len()), "🔄");
+    Ok(config)
+}
+
+/// Rejects unknown transform names and bad transform options up front rather
+/// than on the first payload.
+fn validate(config: &MappingConfig) -> Result<(), String> {
+    for mapping in &config.mappings {
+        for (field, source) in &mapping.fields {
+            if let FieldSource::Spec(spec) = source {
+                for transform in &spec.transforms {
+                    transform.resolve()
+                        .and_then(|t| t.validate())
+                        .map_err(|e| {
+                            let label = mapping.name.as_deref().unwrap_or(&mapping.event);
+                            format!("mapping '{}', field '{}': {}", label, field, e)
+                        })?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Builds the outbound object for a payload. Events without a mapping get
+/// their `data` forwarded unchanged.
+pub fn apply(payload: &Payload) -> Result<Value, Vec<FieldError>> {
+    let config = current();
+    match find(&config, payload, None) {
+        Some(mapping) => apply_mapping(mapping, payload),
+        None => Ok(payload.data.clone().unwrap_or(Value::Null)),
+    }
+}
+
+/// Builds the outbound object with the mapping called `name`.
+pub fn apply_named(payload: &Payload, name: &str) -> Result<Value, Vec<FieldError>> {
+    let config = current();
+    match find(&config, payload, Some(name)) {
+        Some(mapping) => apply_mapping(mapping, payload),
+        None => Err(vec![FieldError { field: String::new(), error: format!("No mapping named '{}'", name) }]),
+    }
+}
+
+/// Whether the mappings file has a mapping called `name`.
+pub fn has_named(name: &str) -> bool {
+    current().mappings.iter().any(|m| m.name.as_deref() == Some(name))
+}
+
+/// The script of the mapping `apply` (or `apply_named` with `name`) uses.
+pub fn script_for(payload: &Payload, name: Option<&str>) -> Option<String> {
+    find(&current(), payload, name)?.script.clone()
+}
+
+/// The mapping called `name`, or else the first one matching the event.
+fn find<'a>(config: &'a MappingConfig, payload: &Payload, name: Option<&str>) -> Option<&'a EventMapping> {
+    if let Some(name) = name {
+        return config.mappings.iter().find(|m| m.name.as_deref() == Some(name));
+    }
+    let event = event_name(payload).unwrap_or("");
+    let rules_only = |m: &EventMapping| m.event.is_empty() && m.name.is_some();
+    config.mappings.iter().find(|m| !rules_only(m) && glob_match(&m.event, event))
+}
+
+/// Reads a path from a payload: relative to `data`, or the whole payload for `$.` paths.
+pub fn resolve_in(payload: &Payload, path: &str) -> Option<Value> {
+    match path.strip_prefix("$.") {
+        Some(path) => resolve(&serde_json::to_value(payload).ok()?, path),
+        None => resolve(payload.data.as_ref()?, path),
+    }
+}
+
+pub fn apply_mapping(mapping: &EventMapping, payload: &Payload) -> Result<Value, Vec<FieldError>> {
+    let data = payload.data.clone().unwrap_or(Value::Null);
+    let root = serde_json::to_value(payload).unwrap_or(Value::Null);
+
+    let mut out = match (&data, mapping.passthrough) {
+        (Value::Object(object), true) => object.clone(),
+        _ => Map::new(),
+    };
+    let mut errors = Vec::new();
+
+    for (field, source) in &mapping.fields {
+        let spec = match source {
+            FieldSource::Path(path) => FieldSpec { path: Some(path.clone()), ..FieldSpec::default() },
+            FieldSource::Spec(spec) => spec.clone(),
+        };
+
+        let lookup = |path: &str| match path.strip_prefix("$.") {
+            Some(path) => resolve(&root, path),
+            None => resolve(&data, path),
+        };
+        let value = match (&spec.constant, &spec.path) {
+            (Some(constant), _) => Some(constant.clone()),
+            (None, Some(path)) => lookup(path),
+            (None, None) => None,
+        };
+        let mut value = value.filter(|v| !v.is_null()).or_else(|| spec.default.clone());
+
+        let mut failed = false;
+        for transform in &spec.transforms {
+            match transform.resolve().and_then(|t| t.apply(value.take(), &lookup).map_err(|e| format!("{} failed: {}", t, e))) {
+                Ok(transformed) => value = transformed,
+                Err(error) => {
+                    errors.push(FieldError { field: field.clone(), error });
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        if failed {
+            continue;
+        }
+
+        match value.filter(|v| !v.is_null()) {
+            Some(value) => insert_nested(&mut out, field, value),
+            None if spec.required => errors.push(FieldError {
+                field: field.clone(),
+                error: format!("No value at '{}'", spec.path.as_deref().unwrap_or("")),
+            }),
+            None => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Value::Object(out))
+    } else {
+        Err(errors)
+    }
+}
+
+/// Resolves a dotted path with `[n]` indexes and `[*]` wildcards. A wildcard
+/// maps the rest of the path over every element and yields an array.
+pub fn resolve(value: &Value, path: &str) -> Option<Value> {
+    let segments = parse_path(path);
+    resolve_segments(value, &segments)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+fn parse_path(path: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for part in path.split('.').filter(|p| !p.is_empty()) {
+        let (key, mut rest) = match part.find('[') {
+            Some(idx) => (&part[..idx], &part[idx..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_string()));
+        }
+        while let Some(end) = rest.find(']') {
+            let inner = &rest[1..end];
+            match inner {
+                "*" => segments.push(Segment::Wildcard),
+                _ => match inner.parse() {
+                    Ok(index) => segments.push(Segment::Index(index)),
+                    Err(_) => segments.push(Segment::Key(inner.trim_matches('"').to_string())),
+                },
+            }
+            rest = &rest[end + 1..];
+        }
+    }
+    segments
+}
+
+fn resolve_segments(value: &Value, segments: &[Segment]) -> Option<Value> {
+    let Some((first, rest)) = segments.split_first() else {
+        return Some(value.clone());
+    };
+    match first {
+        Segment::Key(key) => resolve_segments(value.get(key)?, rest),
+        Segment::Index(index) => resolve_segments(value.get(*index)?, rest),
+        Segment::Wildcard => {
+            let items = value.as_array()?;
+            let mapped: Vec<Value> = items.iter()
+                .filter_map(|item| resolve_segments(item, rest))
+                .flat_map(|v| match v {
+                    // Nested wildcards flatten into one list.
+                    Value::Array(inner) if rest.contains(&Segment::Wildcard) => inner,
+                    other => vec![other],
+                })
+                .collect();
+            Some(Value::Array(mapped))
+        }
+    }
+}
+
+fn insert_nested(out: &mut Map<String, Value>, field: &str, value: Value) {
+    let mut parts = field.split('.').peekable();
+    let mut current = out;
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            current.insert(part.to_string(), value);
+            return;
+        }
+        let entry = current.entry(part.to_string()).or_insert_with(|| Value::Object(Map::new()));
+        if !entry.is_object() {
+            *entry = Value::Object(Map::new());
+        }
+        current = entry.as_object_mut().unwrap();
+    }
+}
@@ -1,15 +1,37 @@
+use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, HttpRequest};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use std::env;
 use std::fs::{read_to_string, write, create_dir_all};
 use chrono::{Local, DateTime, Duration};
 use std::collections::HashMap;
 
+use crate::circuit_breaker::{self, CircuitState};
+use crate::coalesce;
+use crate::dead_letter;
+use crate::delivery::{self, Outcome};
+use crate::field_map;
+use crate::history::{self, DeliveryState};
+use crate::idempotency;
+use crate::idmap;
 use crate::log_msg;
+use crate::metrics;
+use crate::plugin::{self, PluginRun, Verdict};
+use crate::preflight;
+use crate::queue::{self, Delivery};
+use crate::rate_limit;
+use crate::response::{self, Envelope};
+use crate::retry::is_retryable_status;
+use crate::routing::{self, Target};
+use crate::rules::{self, Action, Decision};
+use crate::schema::{self, SchemaMode};
+use crate::script::{self, ScriptOutcome};
+use crate::sink;
+use crate::source::{self, SourceFormat};
 use crate::LOG_FILE_PATH;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Payload {
     #[serde(default)]
     pub data: Option<serde_json::Value>,
@@ -17,131 +39,584 @@ pub struct Payload {
     pub _extra: HashMap<String, serde_json::Value>,
 }
 
-pub async fn post_handler(req: HttpRequest, payload: web::Json<Payload>) -> HttpResponse {
-    // Check API security if enabled
-    if env::var("API_SECURITY").unwrap_or_else(|_| "false".into()) == "true" {
-        let header_key = env::var("API_KEY_HEADER").unwrap_or_else(|_| "x-api-key".to_string());
-        let expected_api_key = if let Ok(key) = env::var("SUBCONTRACTOR_API_KEY") {
-            key
-        } else if env::var("USE_JOB_NIMBUS_AS_SUBCONTRACTOR_KEY").unwrap_or_else(|_| "false".into()) == "true" {
-            env::var("JOB_NIMBUS_API_KEY").unwrap_or_default()
-        } else {
-            env::var("JOB_NIMBUS_API_KEY").unwrap_or_default()
-        };
+/// Checks the API key header when API_SECURITY is enabled.
+fn api_authorized(req: &HttpRequest) -> bool {
+    if env::var("API_SECURITY").unwrap_or_else(|_| "false".into()) != "true" {
+        return true;
+    }
+    let header_key = env::var("API_KEY_HEADER").unwrap_or_else(|_| "x-api-key".to_string());
+    #[allow(clippy::if_same_then_else)]
+    let expected_api_key = if let Ok(key) = env::var("SUBCONTRACTOR_API_KEY") {
+        key
+    } else if env::var("USE_JOB_NIMBUS_AS_SUBCONTRACTOR_KEY").unwrap_or_else(|_| "false".into()) == "true" {
+        env::var("JOB_NIMBUS_API_KEY").unwrap_or_default()
+    } else {
+        env::var("JOB_NIMBUS_API_KEY").unwrap_or_default()
+    };
 
-        match req.headers().get(&header_key) {
-            Some(val) if val.to_str().unwrap_or("") == expected_api_key => { /* OK */ }
-            _ => {
-                log_msg("Unauthorized API access attempt.", "❌");
-                return HttpResponse::Unauthorized().json(serde_json::json!({
-                    "error": "Unauthorized"
-                }));
-            }
-        }
+    match req.headers().get(&header_key) {
+        Some(val) => val.to_str().unwrap_or("") == expected_api_key,
+        None => false,
+    }
+}
+
+/// Whether payloads are acknowledged before forwarding (`ACCEPT_MODE=async`).
+fn accept_async() -> bool {
+    env::var("ACCEPT_MODE").map(|m| m.trim().eq_ignore_ascii_case("async")).unwrap_or(false)
+}
+
+pub async fn post_handler(req: HttpRequest, payload: web::Json<Payload>, client: web::Data<Client>) -> HttpResponse {
+    ingest(&req, payload.into_inner(), &client, None).await
+}
+
+/// Runs one inbound event through the pipeline, whichever endpoint it came
+/// in on. Events from a batch pass their position so they get separate
+/// idempotency keys.
+async fn ingest(req: &HttpRequest, payload: Payload, client: &Client, batch_index: Option<usize>) -> HttpResponse {
+    // Check API security if enabled
+    if !api_authorized(req) {
+        log_msg("Unauthorized API access attempt.", "❌");
+        return unauthorized_json();
     }
 
     log_msg(&format!("Received payload: {:?}", payload), "📥");
 
+    // Noise events are acknowledged so the sender doesn't retry them, whatever
+    // else is wrong with them.
+    if let Some(reason) = routing::ignore_reason(routing::event_name(&payload)) {
+        metrics::incr("sch2jn_payloads_received_total");
+        metrics::incr("sch2jn_events_ignored_total");
+        log_msg(&format!("Ignoring payload: {}", reason), "🙈");
+        return HttpResponse::Ok()
+            .content_type("application/json")
+            .body(Envelope::without_delivery("ignored").with_message(&reason).to_json());
+    }
+
     if payload.data.is_none() {
         log_msg("Missing 'data' in input payload.", "❌");
         return HttpResponse::BadRequest().json(serde_json::json!({
             "error": "Missing 'data' in payload"
         }));
     }
+    metrics::incr("sch2jn_payloads_received_total");
+
+    // Redelivered webhooks get the original answer instead of a second contact.
+    let idempotency_key = idempotency::enabled().then(|| idempotency::key_for_item(req, &payload, batch_index));
+    let _claim = match idempotency_key.as_deref().map(idempotency::claim) {
+        None => None,
+        Some(Ok(claim)) => Some(claim),
+        Some(Err(None)) => {
+            metrics::incr("sch2jn_dedupe_hits_total");
+            log_msg("Duplicate webhook arrived while the original is still being processed", "🔁");
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A request with this idempotency key is already being processed"
+            }));
+        }
+        Some(Err(Some(record))) => {
+            metrics::incr("sch2jn_dedupe_hits_total");
+            log_msg(
+                &format!("Duplicate webhook ignored; already handled as delivery {}", record.delivery_id),
+                "🔁",
+            );
+            let (status, body) = match (record.status, record.body) {
+                (Some(status), Some(body)) => (status, body),
+                _ => (202, Envelope::new("queued", &record.delivery_id)
+                    .with_message("Original delivery is still in progress")
+                    .to_json()),
+            };
+            return HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::OK))
+                .content_type("application/json")
+                .append_header(("Idempotent-Replayed", "true"))
+                .body(body);
+        }
+    };
+
+    process(payload, client, idempotency_key, batch_index.is_some()).await.unwrap_or_else(|refused| refused)
+}
+
+/// Takes an accepted event from schema checks through to its first delivery
+/// attempt, or only as far as the queue with `queue_only`. Dead letters that
+/// never got a body are replayed through here. `Err` is an answer refusing
+/// the event without keeping it anywhere, so the caller still owns it.
+async fn process(
+    payload: Payload,
+    client: &Client,
+    idempotency_key: Option<String>,
+    queue_only: bool,
+) -> Result<HttpResponse, HttpResponse> {
+    // Payloads are checked against their event's schema as they came in.
+    let report = match schema::validate(&payload) {
+        Ok(report) => report,
+        Err(broken) => {
+            metrics::incr("sch2jn_schema_errors_total");
+            if broken.mode == SchemaMode::Warn {
+                log_msg(&format!("Schema {} failed to load, payload not checked: {}", broken.schema, broken.error), "⚠️");
+                None
+            } else {
+                // The sender should try again once the schema is fixed.
+                log_msg(&format!("Schema {} failed to load, payload refused: {}", broken.schema, broken.error), "❌");
+                return Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                    "error": "Schema for this event failed to load",
+                    "schema": broken.schema
+                })));
+            }
+        }
+    };
+    if let Some(report) = report.filter(|report| !report.violations.is_empty()) {
+        let summary: Vec<String> = report.violations
+            .iter()
+            .map(|v| format!("{}: {}", if v.pointer.is_empty() { "/" } else { &v.pointer }, v.message))
+            .collect();
+        if report.mode == SchemaMode::Warn {
+            metrics::incr("sch2jn_schema_warnings_total");
+            log_msg(&format!("Payload violates schema {} (warn only): {}", report.schema, summary.join("; ")), "⚠️");
+        } else {
+            metrics::incr("sch2jn_schema_rejections_total");
+            log_msg(&format!("Payload rejected by schema {}: {}", report.schema, summary.join("; ")), "❌");
+            return Err(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "Schema validation failed",
+                "schema": report.schema,
+                "errors": report.violations
+            })));
+        }
+    }
+
+    // Plugins see the event first, so rules and mappings see what they made of
+    // it. They run off the async workers, on a copy so a failure keeps the
+    // event as it came in.
+    let original = payload.clone();
+    let (payload, verdict) = web::block(move || {
+        let mut payload = payload;
+        let verdict = plugin::run(&mut payload);
+        (payload, verdict)
+    })
+    .await
+    .unwrap_or_else(|e| (original.clone(), (Verdict::Failed(format!("Plugins: {}", e)), Vec::new())));
+    let plugin_runs = match verdict {
+        (Verdict::Keep, runs) => runs,
+        (Verdict::Filtered(name), runs) => {
+            let delivery = plugin_delivery(payload, runs);
+            metrics::incr("sch2jn_plugin_filtered_total");
+            let reason = format!("Filtered out by plugin '{}'", name);
+            return Ok(acknowledge_drop(&delivery, &reason, idempotency_key.as_deref()));
+        }
+        (Verdict::Failed(error), runs) => {
+            let delivery = plugin_delivery(original, runs);
+            metrics::incr("sch2jn_plugin_errors_total");
+            return Ok(park_unsent(&delivery, &error, idempotency_key.as_deref()));
+        }
+    };
+
+    // Routing rules run before anything is mapped or queued.
+    let decision = rules::evaluate(&payload);
+    let target = decision.target.unwrap_or_else(|| routing::route(routing::event_name(&payload)));
+    if decision.action == Action::Drop {
+        let delivery = unsent_delivery(payload, target, &decision, &plugin_runs);
+        log_msg(&format!("Delivery {}: {}", delivery.id, decision.describe()), "🧭");
+        metrics::incr("sch2jn_rule_drops_total");
+        let reason = format!("Dropped by rule '{}'", decision.rule.as_deref().unwrap_or_default());
+        return Ok(acknowledge_drop(&delivery, &reason, idempotency_key.as_deref()));
+    }
+
+    // The mappings file can change after the rules were checked against it.
+    // Such events are parked until the mapping is back.
+    if let Some(name) = decision.mapping.as_deref().filter(|name| !field_map::has_named(name)) {
+        let error = format!(
+            "Rule '{}' names unknown mapping '{}'",
+            decision.rule.as_deref().unwrap_or_default(),
+            name
+        );
+        let delivery = unsent_delivery(payload, target, &decision, &plugin_runs);
+        return Ok(park_unsent(&delivery, &error, idempotency_key.as_deref()));
+    }
+
+    let mapped = match &decision.mapping {
+        Some(name) => field_map::apply_named(&payload, name),
+        None => field_map::apply(&payload),
+    };
+    let mut forward_payload = match mapped {
+        Ok(outbound) => outbound,
+        Err(errors) => {
+            let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+            log_msg(&format!("Field mapping failed for: {}", fields.join(", ")), "❌");
+            return Err(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "Field mapping failed",
+                "fields": errors
+            })));
+        }
+    };
+
+    // A mapping's script gets the final say over what is sent.
+    if let Some(name) = field_map::script_for(&payload, decision.mapping.as_deref()) {
+        // Scripts are CPU bound, so they run off the async workers.
+        let (script, script_payload) = (name.clone(), payload.clone());
+        let outcome = web::block(move || script::run(&script, &script_payload, forward_payload))
+            .await
+            .unwrap_or_else(|e| Err(format!("Script {}: {}", name, e)));
+        match outcome {
+            Ok(ScriptOutcome::Send(outbound)) => forward_payload = outbound,
+            Ok(ScriptOutcome::Drop(reason)) => {
+                let delivery = unsent_delivery(payload, target, &decision, &plugin_runs);
+                metrics::incr("sch2jn_script_drops_total");
+                let reason = format!("Dropped by script '{}': {}", name, reason);
+                return Ok(acknowledge_drop(&delivery, &reason, idempotency_key.as_deref()));
+            }
+            Err(error) => {
+                let delivery = unsent_delivery(payload, target, &decision, &plugin_runs);
+                metrics::incr("sch2jn_script_errors_total");
+                return Ok(park_unsent(&delivery, &error, idempotency_key.as_deref()));
+            }
+        }
+    }
+    // Catch contacts JobNimbus would refuse before queueing them.
+    if target == Target::Contacts && preflight::enabled() {
+        if let Err(errors) = preflight::check_contact(client, &forward_payload).await {
+            let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+            metrics::incr("sch2jn_preflight_rejections_total");
+            log_msg(&format!("Preflight check failed for: {}", fields.join(", ")), "❌");
+            return Err(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "Contact would be rejected by JobNimbus",
+                "fields": errors
+            })));
+        }
+    }
 
-    let forward_payload = payload.data.clone().unwrap();
     let json_payload = match serde_json::to_string_pretty(&forward_payload) {
         Ok(json) => json,
         Err(e) => {
             log_msg(&format!("Error encoding forward payload to JSON: {}", e), "❌");
-            return HttpResponse::InternalServerError().json(serde_json::json!({
+            return Err(HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to encode payload"
-            }));
+            })));
         }
     };
 
-    log_msg("Forwarding payload to Job Nimbus...", "📤");
+    let test_mode = env::var("TEST_MODE").unwrap_or_default() == "true";
+    let needs_api_key = sink::configured().iter().any(|sink| sink.uses_jobnimbus());
+    if !test_mode && needs_api_key && env::var("JOB_NIMBUS_API_KEY").is_err() {
+        log_msg("JOB_NIMBUS_API_KEY not set in environment.", "❌");
+        return Err(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Server configuration error"
+        })));
+    }
 
-    if env::var("TEST_MODE").unwrap_or_default() == "true" {
-        log_msg("Simulated forwarding in test mode.", "🧪");
-        return HttpResponse::Ok().json(serde_json::json!({
-            "status": "ok",
-            "message": "Test forward successful"
-        }));
+    // Persist before doing anything else so the event survives an outage or restart.
+    let mut delivery = unsent_delivery(payload, target, &decision, &plugin_runs);
+    delivery.body = json_payload;
+    if decision.rule.is_some() {
+        log_msg(&format!("Delivery {}: {}", delivery.id, decision.describe()), "🧭");
     }
 
-    let api_key = match env::var("JOB_NIMBUS_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
-            log_msg("JOB_NIMBUS_API_KEY not set in environment.", "❌");
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Server configuration error"
-            }));
-        }
-    };
+    if decision.action == Action::Hold {
+        return Ok(hold_by_rule(&delivery, idempotency_key.as_deref()));
+    }
 
-    let client = Client::new();
-    let api_url = "https://app.jobnimbus.com/api1/contacts";
-
-    let res = client.post(api_url)
-        .header("Authorization", format!("bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .body(json_payload.clone())
-        .send()
-        .await;
-
-    let (response_text, status_code) = match res {
-        Ok(response) => {
-            let status_code = response.status().as_u16();
-            let text = match response.text().await {
-                Ok(text) => text,
-                Err(e) => {
-                    log_msg(&format!("Failed to read response: {}", e), "❌");
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Failed to read response"
-                    }));
+    // Rapid updates to the same record are folded into one pending delivery.
+    // The first update waits out the window for others to merge; the worker
+    // sends it once it is due.
+    if let Some(window) = coalesce::window().filter(|_| coalesce::merge_key(&delivery).is_some()) {
+        delivery.next_attempt_at = Some(Local::now() + window);
+        return match coalesce::merge_or_enqueue(&delivery) {
+            Ok(Some(merged)) => {
+                history::record_merged(&delivery, &merged.id);
+                let body = Envelope::new("queued", &merged.id)
+                    .with_message(&format!("Merged into pending delivery {}", merged.id))
+                    .to_json();
+                if let Some(key) = &idempotency_key {
+                    idempotency::remember(key, &merged.id, StatusCode::ACCEPTED.as_u16(), &body);
                 }
-            };
-            (text, status_code)
-        }
+                Ok(HttpResponse::Accepted()
+                    .content_type("application/json")
+                    .append_header(("Location", format!("/deliveries/{}", merged.id)))
+                    .body(body))
+            }
+            Ok(None) => {
+                if let Some(key) = &idempotency_key {
+                    idempotency::reserve(key, &delivery.id);
+                }
+                log_msg(
+                    &format!(
+                        "Delivery {} held for {}s to merge further updates to the same record",
+                        delivery.id,
+                        window.num_seconds()
+                    ),
+                    "⏳",
+                );
+                Ok(HttpResponse::Accepted()
+                    .content_type("application/json")
+                    .append_header(("Location", format!("/deliveries/{}", delivery.id)))
+                    .body(Envelope::new("queued", &delivery.id)
+                        .with_message(&format!("Held for {}s to merge further updates", window.num_seconds()))
+                        .to_json()))
+            }
+            Err(e) => Err(persist_failed(&e)),
+        };
+    }
+
+    queue::claim(&delivery.id);
+    if let Err(e) = queue::persist(&delivery) {
+        queue::release(&delivery.id);
+        return Err(persist_failed(&e));
+    }
+    if let Some(key) = &idempotency_key {
+        idempotency::reserve(key, &delivery.id);
+    }
+
+    // Events from a batch are left to the worker, so a big batch doesn't hold
+    // the request open for one forward after another.
+    if queue_only {
+        queue::release(&delivery.id);
+        log_msg(&format!("Delivery {} queued for the worker", delivery.id), "📥");
+        return Ok(HttpResponse::Accepted()
+            .content_type("application/json")
+            .append_header(("Location", format!("/deliveries/{}", delivery.id)))
+            .body(Envelope::new("queued", &delivery.id).with_message("Queued for delivery").to_json()));
+    }
+
+    log_msg(
+        &format!("Forwarding payload to Job Nimbus {} (delivery {})...", delivery.target, delivery.id),
+        "📤",
+    );
+
+    // In async mode the sender gets its answer now and the forward carries on
+    // in the background; duplicates are answered with the final result.
+    if accept_async() {
+        let id = delivery.id.clone();
+        let client = client.clone();
+        actix_web::rt::spawn(async move {
+            let (status, envelope) = forward(&client, &mut delivery, test_mode).await;
+            if let Some(key) = &idempotency_key {
+                idempotency::remember(key, &delivery.id, status.as_u16(), &envelope.to_json());
+            }
+        });
+        return Ok(HttpResponse::Accepted()
+            .content_type("application/json")
+            .append_header(("Location", format!("/deliveries/{}", id)))
+            .body(Envelope::new("queued", &id).with_message("Accepted for delivery").to_json()));
+    }
+
+    // Whatever the answer, the delivery is now delivered, queued or in the
+    // dead letters, so a retry is answered the same rather than sent again.
+    // Discarding the dead letter lets the next retry through.
+    let (status, envelope) = forward(client, &mut delivery, test_mode).await;
+    let body = envelope.to_json();
+    if let Some(key) = &idempotency_key {
+        idempotency::remember(key, &delivery.id, status.as_u16(), &body);
+    }
+    Ok(HttpResponse::build(status)
+        .content_type("application/json")
+        .body(body))
+}
+
+/// `POST /in/{source}`: accepts events in the format of a configured source
+/// and runs each through the same pipeline as `POST /`.
+pub async fn source_handler(
+    req: HttpRequest,
+    name: web::Path<String>,
+    body: web::Bytes,
+    client: web::Data<Client>,
+) -> HttpResponse {
+    if !api_authorized(&req) {
+        log_msg("Unauthorized API access attempt.", "❌");
+        return unauthorized_json();
+    }
+    let Some(config) = source::find(&name) else {
+        log_msg(&format!("Payload posted to unknown source '{}'", name), "❌");
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown source '{}'", name)
+        }));
+    };
+    let payloads = match source::normalize(&name, &config, &body) {
+        Ok(payloads) => payloads,
         Err(e) => {
-            log_msg(&format!("HTTP request error: {}", e), "❌");
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to forward payload"
-            }));
+            log_msg(&format!("Rejected payload from source '{}': {}", name, e), "❌");
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
         }
     };
 
-    log_msg(&format!("Received response from Job Nimbus (HTTP {})", status_code), "📬");
-    log_msg(&format!("Response: {}", response_text), "📬");
+    if config.format != SourceFormat::Batch {
+        let Some(payload) = payloads.into_iter().next() else {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "No event in request" }));
+        };
+        return ingest(&req, payload, &client, None).await;
+    }
 
-    HttpResponse::Ok()
+    let max_batch = source::max_batch();
+    if payloads.len() > max_batch {
+        log_msg(&format!("Rejected batch of {} events from source '{}'", payloads.len(), name), "❌");
+        return HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("Batch of {} events exceeds the limit of {}", payloads.len(), max_batch)
+        }));
+    }
+
+    // A batch is answered with each event's own response, in order. Accepted
+    // events are queued for the worker rather than forwarded here.
+    log_msg(&format!("Received batch of {} events from source '{}'", payloads.len(), name), "📦");
+    let mut results = Vec::new();
+    let mut all_ok = true;
+    for (index, payload) in payloads.into_iter().enumerate() {
+        let response = ingest(&req, payload, &client, Some(index)).await;
+        let status = response.status();
+        all_ok &= status.is_success();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap_or_default();
+        results.push(serde_json::json!({
+            "status": status.as_u16(),
+            "body": serde_json::from_slice::<serde_json::Value>(&body).unwrap_or(serde_json::Value::Null)
+        }));
+    }
+    let status = if all_ok { StatusCode::ACCEPTED } else { StatusCode::MULTI_STATUS };
+    HttpResponse::build(status).json(serde_json::json!({ "results": results }))
+}
+
+/// A delivery for `payload` carrying the rule decision and plugin runs, with
+/// nothing to send yet.
+fn unsent_delivery(payload: Payload, target: Target, decision: &Decision, plugins: &[PluginRun]) -> Delivery {
+    let mut delivery = Delivery::new(payload, String::new());
+    delivery.target = target;
+    delivery.rule = decision.rule.clone();
+    delivery.tags = decision.tags.clone();
+    delivery.plugins = plugins.to_vec();
+    delivery
+}
+
+/// A delivery for a payload the plugins stopped before the rules ran.
+fn plugin_delivery(payload: Payload, plugins: Vec<PluginRun>) -> Delivery {
+    let mut delivery = Delivery::new(payload, String::new());
+    delivery.target = routing::route(routing::event_name(&delivery.payload));
+    delivery.plugins = plugins;
+    delivery
+}
+
+/// Parks a payload whose body couldn't be built in the dead letters, so it
+/// can be replayed through the pipeline once the cause is fixed. The sender
+/// is told we have it and shouldn't retry.
+fn park_unsent(delivery: &Delivery, error: &str, idempotency_key: Option<&str>) -> HttpResponse {
+    log_msg(&format!("Delivery {} failed: {}", delivery.id, error), "❌");
+    dead_letter::park_unprocessed(delivery, error);
+    history::record(delivery, DeliveryState::Failed, None, Some(error));
+
+    let body = Envelope::new("failed", &delivery.id)
+        .with_message(&format!("{}; parked in the dead letters for replay", error))
+        .to_json();
+    if let Some(key) = idempotency_key {
+        idempotency::remember(key, &delivery.id, StatusCode::ACCEPTED.as_u16(), &body);
+    }
+    HttpResponse::Accepted()
         .content_type("application/json")
-        .body(response_text)
+        .append_header(("Location", format!("/deliveries/{}", delivery.id)))
+        .body(body)
 }
 
-pub async fn logs_handler(req: HttpRequest) -> HttpResponse {
-    if env::var("GUI_AUTH_REQUIRED").unwrap_or_else(|_| "false".into()) == "true" {
-        // Check for password in header "x-gui-password" or as query parameter "password"
-        let provided = req.headers().get("x-gui-password")
-            .and_then(|v| v.to_str().ok())
-            .or_else(|| {
-                req.uri().query().and_then(|q| {
-                    q.split('&')
-                     .find_map(|param| {
-                         let mut parts = param.splitn(2, '=');
-                         if parts.next()? == "password" {
-                             return parts.next();
-                         }
-                         None
-                     })
-                })
-            });
-        if provided != Some(env::var("GUI_PASSWORD").unwrap_or_default().as_str()) {
-            return HttpResponse::Unauthorized()
-                .content_type("text/plain; charset=utf-8")
-                .body("Unauthorized");
+/// Acknowledges a payload that won't be sent, keeping a record of it.
+fn acknowledge_drop(delivery: &Delivery, reason: &str, idempotency_key: Option<&str>) -> HttpResponse {
+    history::record(delivery, DeliveryState::Dropped, None, Some(reason));
+    log_msg(&format!("Delivery {}: {}", delivery.id, reason), "🗑️");
+
+    let body = Envelope::new("dropped", &delivery.id).with_message(reason).to_json();
+    if let Some(key) = idempotency_key {
+        idempotency::remember(key, &delivery.id, StatusCode::OK.as_u16(), &body);
+    }
+    HttpResponse::Ok().content_type("application/json").body(body)
+}
+
+/// Parks a payload in the dead-letter store for review instead of sending it.
+/// Replaying it from there sends it as usual.
+fn hold_by_rule(delivery: &Delivery, idempotency_key: Option<&str>) -> HttpResponse {
+    let reason = format!("Held for review by rule '{}'", delivery.rule.as_deref().unwrap_or_default());
+    metrics::incr("sch2jn_rule_holds_total");
+    dead_letter::park(delivery, &reason, None);
+    history::record(delivery, DeliveryState::Held, None, Some(&reason));
+
+    let body = Envelope::new("held", &delivery.id).with_message(&reason).to_json();
+    if let Some(key) = idempotency_key {
+        idempotency::remember(key, &delivery.id, StatusCode::ACCEPTED.as_u16(), &body);
+    }
+    HttpResponse::Accepted()
+        .content_type("application/json")
+        .append_header(("Location", format!("/deliveries/{}", delivery.id)))
+        .body(body)
+}
+
+fn persist_failed(error: &std::io::Error) -> HttpResponse {
+    log_msg(&format!("Failed to persist payload to queue: {}", error), "❌");
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": "Failed to persist payload"
+    }))
+}
+
+/// Makes the first attempt at a freshly accepted, claimed delivery.
+async fn forward(client: &Client, delivery: &mut Delivery, test_mode: bool) -> (StatusCode, Envelope) {
+    if test_mode {
+        queue::complete(&delivery.id);
+        history::record(delivery, DeliveryState::Succeeded, None, None);
+        log_msg("Simulated forwarding in test mode.", "🧪");
+        (StatusCode::OK, Envelope::new("ok", &delivery.id).with_message("Test forward successful"))
+    } else {
+        let (status, envelope) = match delivery::attempt(client, delivery).await {
+            Outcome::Delivered(response) => {
+                log_msg(&format!("Received response from Job Nimbus (HTTP {})", response.status), "📬");
+                log_msg(&format!("Response: {}", response.body), "📬");
+                (response::inbound_status(response.status), Envelope::new("delivered", &delivery.id).with_upstream(&response))
+            }
+            Outcome::Failed { error, response: Some(response) } => {
+                log_msg(&format!("Received response from Job Nimbus (HTTP {})", response.status), "📬");
+                log_msg(&format!("Response: {}", response.body), "📬");
+                let status = if is_retryable_status(response.status) { "failed" } else { "rejected" };
+                (
+                    response::inbound_status(response.status),
+                    Envelope::new(status, &delivery.id).with_upstream(&response).with_message(&error),
+                )
+            }
+            Outcome::Retrying { error } => (
+                StatusCode::ACCEPTED,
+                Envelope::new("queued", &delivery.id).with_message(&format!("Payload queued for delivery: {}", error)),
+            ),
+            Outcome::Dropped { reason } => (
+                StatusCode::OK,
+                Envelope::new("dropped", &delivery.id).with_message(&reason),
+            ),
+            Outcome::Failed { error, response: None } => (
+                StatusCode::BAD_GATEWAY,
+                Envelope::new("failed", &delivery.id).with_message(&error),
+            ),
+        };
+        // Fanned out deliveries also report how each sink fared.
+        if delivery.sinks.len() > 1 {
+            return (status, envelope.with_sinks(&delivery.sinks));
         }
+        (status, envelope)
+    }
+}
+
+/// Checks the GUI password when GUI_AUTH_REQUIRED is enabled.
+fn gui_authorized(req: &HttpRequest) -> bool {
+    if env::var("GUI_AUTH_REQUIRED").unwrap_or_else(|_| "false".into()) != "true" {
+        return true;
+    }
+    // Check for password in header "x-gui-password" or as query parameter "password"
+    let provided = req.headers().get("x-gui-password")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            req.uri().query().and_then(|q| {
+                q.split('&')
+                 .find_map(|param| {
+                     let mut parts = param.splitn(2, '=');
+                     if parts.next()? == "password" {
+                         return parts.next();
+                     }
+                     None
+                 })
+            })
+        });
+    provided == Some(env::var("GUI_PASSWORD").unwrap_or_default().as_str())
+}
+
+#[allow(clippy::redundant_pattern_matching)]
+pub async fn logs_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return HttpResponse::Unauthorized()
+            .content_type("text/plain; charset=utf-8")
+            .body("Unauthorized");
     }
     let _lock = crate::LOG_LOCK.lock().unwrap();
     let file_path = std::path::Path::new(LOG_FILE_PATH);
@@ -221,6 +696,7 @@ pub async fn static_file_handler(req: HttpRequest) -> HttpResponse {
     
     match std::fs::read(&file_path) {
         Ok(content) => {
+            #[allow(clippy::double_ended_iterator_last)]
             let content_type = match file_path.split('.').last() {
                 Some("css") => "text/css",
                 Some("js") => "application/javascript",
@@ -240,25 +716,8 @@ pub async fn static_file_handler(req: HttpRequest) -> HttpResponse {
 }
 
 pub async fn index_handler(req: HttpRequest) -> HttpResponse {
-    if env::var("GUI_AUTH_REQUIRED").unwrap_or_else(|_| "false".into()) == "true" {
-        // Check for password in header "x-gui-password" or as query parameter "password"
-        let provided = req.headers().get("x-gui-password")
-            .and_then(|v| v.to_str().ok())
-            .or_else(|| {
-                req.uri().query().and_then(|q| {
-                    q.split('&')
-                     .find_map(|param| {
-                         let mut parts = param.splitn(2, '=');
-                         if parts.next()? == "password" {
-                             return parts.next();
-                         }
-                         None
-                     })
-                })
-            });
-        if provided != Some(env::var("GUI_PASSWORD").unwrap_or_default().as_str()) {
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+    if !gui_authorized(&req) {
+        return HttpResponse::Unauthorized().body("Unauthorized");
     }
     match std::fs::read_to_string("static/index.html") {
         Ok(content) => HttpResponse::Ok().content_type("text/html").body(content),
@@ -393,3 +852,287 @@ pub async fn clear_logs_handler() -> HttpResponse {
         }
     }
 }
+
+#[derive(Deserialize)]
+pub struct DeadLetterEdit {
+    pub body: serde_json::Value,
+}
+
+fn unauthorized_json() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": "Unauthorized"
+    }))
+}
+
+fn dead_letter_not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": format!("Dead letter {} not found", id)
+    }))
+}
+
+pub async fn dead_letters_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    let summaries: Vec<serde_json::Value> = dead_letter::list()
+        .into_iter()
+        .rev()
+        .map(|letter| serde_json::json!({
+            "id": letter.id,
+            "received_at": letter.received_at,
+            "failed_at": letter.failed_at,
+            "reason": letter.reason,
+            "target": letter.target,
+            "attempts": letter.attempts,
+            "upstream_status": letter.upstream_status,
+        }))
+        .collect();
+    HttpResponse::Ok().json(summaries)
+}
+
+pub async fn dead_letter_handler(req: HttpRequest, id: web::Path<String>) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    match dead_letter::get(&id) {
+        Some(letter) => HttpResponse::Ok().json(letter),
+        None => dead_letter_not_found(&id),
+    }
+}
+
+pub async fn edit_dead_letter_handler(
+    req: HttpRequest,
+    id: web::Path<String>,
+    edit: web::Json<DeadLetterEdit>,
+) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    let mut letter = match dead_letter::get(&id) {
+        Some(letter) => letter,
+        None => return dead_letter_not_found(&id),
+    };
+
+    letter.body = match serde_json::to_string_pretty(&edit.body) {
+        Ok(body) => body,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid body: {}", e)
+            }));
+        }
+    };
+    // An edited body is sent as given rather than rebuilt on replay.
+    letter.unprocessed = false;
+    if let Err(e) = dead_letter::save(&letter) {
+        log_msg(&format!("Failed to update dead letter {}: {}", letter.id, e), "❌");
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update dead letter"
+        }));
+    }
+    log_msg(&format!("Dead letter {} edited", letter.id), "📝");
+    HttpResponse::Ok().json(letter)
+}
+
+pub async fn replay_dead_letter_handler(req: HttpRequest, id: web::Path<String>, client: web::Data<Client>) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    // A payload that never got a body goes through the pipeline again. The
+    // letter is only let go once the rerun has kept the event somewhere.
+    if let Some(letter) = dead_letter::get(&id).filter(|letter| letter.unprocessed) {
+        log_msg(&format!("Dead letter {} re-submitted to the pipeline", letter.id), "🔄");
+        return match process(letter.payload, &client, None, false).await {
+            Ok(response) => {
+                dead_letter::discard(&letter.id);
+                response
+            }
+            Err(refused) => {
+                log_msg(&format!("Dead letter {} refused again; keeping it", letter.id), "⚠️");
+                refused
+            }
+        };
+    }
+    match dead_letter::replay(&id) {
+        Ok(Some(delivery)) => HttpResponse::Accepted().json(serde_json::json!({
+            "status": "queued",
+            "id": delivery.id
+        })),
+        Ok(None) => dead_letter_not_found(&id),
+        Err(e) => {
+            log_msg(&format!("Failed to replay dead letter {}: {}", id, e), "❌");
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to replay dead letter"
+            }))
+        }
+    }
+}
+
+pub async fn discard_dead_letter_handler(req: HttpRequest, id: web::Path<String>) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    if !dead_letter::discard(&id) {
+        return dead_letter_not_found(&id);
+    }
+    // The sender may try again now that the delivery won't be replayed.
+    idempotency::forget(&id);
+    log_msg(&format!("Dead letter {} discarded", id), "🗑️");
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "message": "Dead letter discarded"
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct MappingQuery {
+    pub sch_id: Option<String>,
+    pub jnid: Option<String>,
+    pub target: Option<String>,
+}
+
+pub async fn mappings_handler(req: HttpRequest, query: web::Query<MappingQuery>) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    let target = match query.target.as_deref().map(Target::parse) {
+        None => Target::Contacts,
+        Some(Some(target)) => target,
+        Some(None) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Unknown target"
+        })),
+    };
+    let mapping = match (&query.sch_id, &query.jnid) {
+        (Some(sch_id), _) => idmap::by_sch_id_for(target, sch_id),
+        (None, Some(jnid)) => idmap::by_jnid(jnid),
+        (None, None) => return HttpResponse::Ok().json(idmap::list()),
+    };
+    match mapping {
+        Some(mapping) => HttpResponse::Ok().json(mapping),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No mapping found"
+        })),
+    }
+}
+
+pub async fn field_mappings_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    HttpResponse::Ok().json(&*field_map::current())
+}
+
+pub async fn reload_field_mappings_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    match field_map::reload() {
+        Ok(config) => HttpResponse::Ok().json(serde_json::json!({
+            "status": "ok",
+            "message": format!("Loaded {} event mappings", config.mappings.len())
+        })),
+        Err(e) => {
+            log_msg(&format!("Failed to reload field mappings: {}", e), "❌");
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid mapping file: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn rules_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    HttpResponse::Ok().json(&*rules::current())
+}
+
+pub async fn reload_rules_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    match rules::reload() {
+        Ok(config) => HttpResponse::Ok().json(serde_json::json!({
+            "status": "ok",
+            "message": format!("Loaded {} routing rules", config.rules.len())
+        })),
+        Err(e) => {
+            log_msg(&format!("Failed to reload routing rules: {}", e), "❌");
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid rules file: {}", e)
+            }))
+        }
+    }
+}
+
+/// `POST /preflight/refresh`: fetches the JobNimbus record types and
+/// statuses preflight checks use, e.g. after changing them in JobNimbus.
+pub async fn refresh_preflight_handler(req: HttpRequest, client: web::Data<Client>) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    match preflight::refresh(&client).await {
+        Ok(workflows) => HttpResponse::Ok().json(serde_json::json!({ "status": "ok", "workflows": workflows })),
+        Err(e) => {
+            log_msg(&format!("Failed to refresh JobNimbus account settings: {}", e), "❌");
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Couldn't fetch JobNimbus account settings: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn schemas_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "schemas": schema::loaded() }))
+}
+
+pub async fn plugins_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "plugins": plugin::loaded() }))
+}
+
+pub async fn metrics_handler() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics::render())
+}
+
+pub async fn delivery_status_handler(req: HttpRequest, id: web::Path<String>) -> HttpResponse {
+    if !api_authorized(&req) {
+        return unauthorized_json();
+    }
+    match history::status(&id) {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Delivery {} not found", id)
+        })),
+    }
+}
+
+/// Live state of the outbound safeguards, polled by the dashboard.
+pub async fn status_handler(req: HttpRequest) -> HttpResponse {
+    if !gui_authorized(&req) {
+        return unauthorized_json();
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "rate_limit": rate_limit::status(),
+        "circuit_breaker": circuit_breaker::status(),
+        "queued": queue::pending().len(),
+    }))
+}
+
+/// Unauthenticated health check. The bridge itself is up whenever this
+/// answers; `degraded` means JobNimbus calls are currently being held back.
+pub async fn health_handler() -> HttpResponse {
+    let circuit = circuit_breaker::status();
+    let status = if circuit.state == CircuitState::Closed { "ok" } else { "degraded" };
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": status,
+        "circuit_breaker": circuit,
+        "queued": queue::pending().len(),
+    }))
+}
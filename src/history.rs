@@ -0,0 +1,193 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+use crate::dead_letter;
+use crate::jobnimbus::UpstreamResponse;
+use crate::log_msg;
+use crate::plugin::PluginRun;
+use crate::queue::{self, Delivery};
+use crate::response::parse_body;
+use crate::routing::Target;
+use crate::sink::SinkResult;
+use crate::store::{self, data_dir};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    Queued,
+    InFlight,
+    Succeeded,
+    Failed,
+    /// Folded into another pending delivery for the same record.
+    Merged,
+    /// Skipped because a newer event for the record was already delivered.
+    Dropped,
+    /// Parked in the dead-letter store by a routing rule for someone to review.
+    Held,
+}
+
+/// Where a delivery stands, as reported by `GET /deliveries/{id}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeliveryStatus {
+    pub id: String,
+    pub status: DeliveryState,
+    pub target: Target,
+    pub received_at: DateTime<Local>,
+    pub attempts: u32,
+    #[serde(default)]
+    pub next_attempt_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub upstream_status: Option<u16>,
+    #[serde(default)]
+    pub upstream_body: Option<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merged: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged_into: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sinks: BTreeMap<String, SinkResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginRun>,
+}
+
+fn history_dir() -> PathBuf {
+    data_dir().join("history")
+}
+
+/// How long finished deliveries stay queryable (`DELIVERY_HISTORY_DAYS`, default 7).
+fn retention() -> Duration {
+    let days = env::var("DELIVERY_HISTORY_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(7);
+    Duration::days(days)
+}
+
+/// Records the final outcome of a delivery.
+pub fn record(delivery: &Delivery, status: DeliveryState, response: Option<&UpstreamResponse>, error: Option<&str>) {
+    let entry = DeliveryStatus {
+        id: delivery.id.clone(),
+        status,
+        target: delivery.target,
+        received_at: delivery.received_at,
+        attempts: delivery.attempts,
+        next_attempt_at: None,
+        finished_at: Some(Local::now()),
+        error: error.map(str::to_string),
+        upstream_status: response.map(|r| r.status),
+        upstream_body: response.map(|r| parse_body(&r.body)),
+        merged: delivery.merged.clone(),
+        merged_into: None,
+        stale: delivery.stale,
+        rule: delivery.rule.clone(),
+        tags: delivery.tags.clone(),
+        sinks: delivery.sinks.clone(),
+        plugins: delivery.plugins.clone(),
+    };
+    write(&entry);
+}
+
+/// Records that a delivery was folded into the pending delivery `into`.
+pub fn record_merged(delivery: &Delivery, into: &str) {
+    write(&DeliveryStatus {
+        id: delivery.id.clone(),
+        status: DeliveryState::Merged,
+        target: delivery.target,
+        received_at: delivery.received_at,
+        attempts: 0,
+        next_attempt_at: None,
+        finished_at: Some(Local::now()),
+        error: None,
+        upstream_status: None,
+        upstream_body: None,
+        merged: Vec::new(),
+        merged_into: Some(into.to_string()),
+        stale: false,
+        rule: delivery.rule.clone(),
+        tags: delivery.tags.clone(),
+        sinks: BTreeMap::new(),
+        plugins: delivery.plugins.clone(),
+    });
+}
+
+fn write(entry: &DeliveryStatus) {
+    if let Err(e) = store::write_json(&history_dir(), &entry.id, entry) {
+        log_msg(&format!("Failed to record outcome of delivery {}: {}", entry.id, e), "⚠️");
+    }
+}
+
+/// The current status of a delivery. Queued deliveries are reported from the
+/// queue itself, so a replayed dead letter shows up as queued again.
+pub fn status(id: &str) -> Option<DeliveryStatus> {
+    if !store::is_valid_id(id) {
+        return None;
+    }
+    if let Some(delivery) = queue::get(id) {
+        let state = if queue::is_claimed(id) { DeliveryState::InFlight } else { DeliveryState::Queued };
+        return Some(DeliveryStatus {
+            id: delivery.id,
+            status: state,
+            target: delivery.target,
+            received_at: delivery.received_at,
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at,
+            finished_at: None,
+            error: delivery.last_error,
+            upstream_status: None,
+            upstream_body: None,
+            merged: delivery.merged,
+            merged_into: None,
+            stale: delivery.stale,
+            rule: delivery.rule,
+            tags: delivery.tags,
+            sinks: delivery.sinks,
+            plugins: delivery.plugins,
+        });
+    }
+    if let Some(entry) = store::read_json(&history_dir(), id) {
+        return Some(entry);
+    }
+    dead_letter::get(id).map(|letter| DeliveryStatus {
+        id: letter.id,
+        status: DeliveryState::Failed,
+        target: letter.target,
+        received_at: letter.received_at,
+        attempts: letter.attempts,
+        next_attempt_at: None,
+        finished_at: Some(letter.failed_at),
+        error: Some(letter.reason),
+        upstream_status: letter.upstream_status,
+        upstream_body: letter.upstream_body.as_deref().map(parse_body),
+        merged: Vec::new(),
+        merged_into: None,
+        stale: false,
+        rule: None,
+        tags: Vec::new(),
+        sinks: letter.sinks,
+        plugins: Vec::new(),
+    })
+}
+
+/// Drops outcomes older than the retention period.
+pub fn prune() {
+    let cutoff = Local::now() - retention();
+    for entry in store::list_json::<DeliveryStatus>(&history_dir()) {
+        if entry.finished_at.is_some_and(|at| at < cutoff) {
+            store::remove_json(&history_dir(), &entry.id);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use reqwest::{Certificate, Client, NoProxy, Proxy};
+use std::env;
+use std::fs::read;
+use std::time::Duration;
+
+fn env_secs(key: &str, default: u64) -> Duration {
+    Duration::from_secs(env::var(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default))
+}
+
+fn env_set(key: &str) -> Option<String> {
+    env::var(key).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Builds the HTTP client shared by the handlers and the delivery worker.
+///
+/// - `HTTP_CONNECT_TIMEOUT_SECS` / `HTTP_REQUEST_TIMEOUT_SECS` bound every call (10s / 30s)
+/// - `HTTP_POOL_MAX_IDLE` idle connections kept per host (10), closed after
+///   `HTTP_POOL_IDLE_TIMEOUT_SECS` (90s)
+/// - `HTTP_USER_AGENT` defaults to `sch2jn/<version>`
+/// - `OUTBOUND_PROXY` sends all calls through a proxy (credentials may go in
+///   the URL), skipping the hosts in `OUTBOUND_NO_PROXY`
+/// - `HTTP_CA_BUNDLE` is a PEM file of extra root certificates to trust
+pub fn build() -> Result<Client, String> {
+    let pool_max_idle: usize = env::var("HTTP_POOL_MAX_IDLE")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(10);
+    let user_agent = env_set("HTTP_USER_AGENT")
+        .unwrap_or_else(|| format!("sch2jn/{}", env!("CARGO_PKG_VERSION")));
+
+    let mut builder = Client::builder()
+        .connect_timeout(env_secs("HTTP_CONNECT_TIMEOUT_SECS", 10))
+        .timeout(env_secs("HTTP_REQUEST_TIMEOUT_SECS", 30))
+        .pool_max_idle_per_host(pool_max_idle)
+        .pool_idle_timeout(env_secs("HTTP_POOL_IDLE_TIMEOUT_SECS", 90))
+        .user_agent(user_agent);
+
+    if let Some(url) = env_set("OUTBOUND_PROXY") {
+        let proxy = Proxy::all(&url)
+            .map_err(|e| format!("Invalid OUTBOUND_PROXY: {}", e))?
+            .no_proxy(env_set("OUTBOUND_NO_PROXY").and_then(|hosts| NoProxy::from_string(&hosts)));
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = env_set("HTTP_CA_BUNDLE") {
+        let pem = read(&path).map_err(|e| format!("Failed to read HTTP_CA_BUNDLE {}: {}", path, e))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Invalid certificate in HTTP_CA_BUNDLE {}: {}", path, e))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
@@ -0,0 +1,152 @@
+use actix_web::HttpRequest;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::handlers::Payload;
+use crate::log_msg;
+use crate::routing;
+use crate::store::{self, data_dir};
+
+/// What we answered the first time we saw an idempotency key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    pub delivery_id: String,
+    pub recorded_at: DateTime<Local>,
+    /// `None` while the original request is still being processed.
+    #[serde(default)]
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+// Keys of requests being processed right now.
+static IN_FLIGHT: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Holds an idempotency key while its request is processed. Dropping it lets
+/// the next request with the key through.
+pub struct Claim(String);
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().remove(&self.0);
+    }
+}
+
+fn idempotency_dir() -> PathBuf {
+    data_dir().join("idempotency")
+}
+
+/// Dedupe window in seconds (`IDEMPOTENCY_WINDOW_SECS`, default one day, 0 disables).
+fn window() -> Duration {
+    let secs = env::var("IDEMPOTENCY_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(86_400);
+    Duration::seconds(secs)
+}
+
+pub fn enabled() -> bool {
+    window() > Duration::zero()
+}
+
+/// Derives the idempotency key for an inbound webhook: the configured header
+/// (`IDEMPOTENCY_HEADER`, default `Idempotency-Key`) when present, otherwise
+/// a hash of the event name and `data` object. For the event at `index` of a
+/// batch the header names the whole request, so each event's key includes its
+/// position.
+pub fn key_for_item(req: &HttpRequest, payload: &Payload, index: Option<usize>) -> String {
+    let header = env::var("IDEMPOTENCY_HEADER").unwrap_or_else(|_| "Idempotency-Key".to_string());
+    let source = match req.headers().get(header.as_str()).and_then(|v| v.to_str().ok()) {
+        Some(value) if !value.trim().is_empty() => match index {
+            Some(index) => format!("header:{}#{}", value.trim(), index),
+            None => format!("header:{}", value.trim()),
+        },
+        _ => {
+            let event = routing::event_name(payload).unwrap_or("");
+            // serde_json sorts object keys, so this is stable for equal payloads.
+            let data = payload.data.as_ref().map(|d| d.to_string()).unwrap_or_default();
+            format!("payload:{}:{}", event, data)
+        }
+    };
+
+    store::hashed_id(&source)
+}
+
+/// The record for `key` if it was seen within the dedupe window.
+pub fn lookup(key: &str) -> Option<IdempotencyRecord> {
+    let record: IdempotencyRecord = store::read_json(&idempotency_dir(), key)?;
+    if Local::now().signed_duration_since(record.recorded_at) >= window() {
+        return None;
+    }
+    Some(record)
+}
+
+/// Claims `key` for the current request. Fails with the stored record if the
+/// key was seen within the dedupe window, or with `None` while another
+/// request with it is still being processed here. The check and the claim
+/// happen under one lock, so concurrent duplicates can't both get through.
+pub fn claim(key: &str) -> Result<Claim, Option<IdempotencyRecord>> {
+    let mut in_flight = IN_FLIGHT.lock().unwrap();
+    if in_flight.contains(key) {
+        return Err(None);
+    }
+    if let Some(record) = lookup(key) {
+        return Err(Some(record));
+    }
+    in_flight.insert(key.to_string());
+    Ok(Claim(key.to_string()))
+}
+
+/// Records that `key` belongs to a new delivery before it is forwarded.
+pub fn reserve(key: &str, delivery_id: &str) {
+    save(IdempotencyRecord {
+        key: key.to_string(),
+        delivery_id: delivery_id.to_string(),
+        recorded_at: Local::now(),
+        status: None,
+        body: None,
+    });
+}
+
+/// Records the response we gave for `key` so redeliveries get the same answer.
+pub fn remember(key: &str, delivery_id: &str, status: u16, body: &str) {
+    save(IdempotencyRecord {
+        key: key.to_string(),
+        delivery_id: delivery_id.to_string(),
+        recorded_at: Local::now(),
+        status: Some(status),
+        body: Some(body.to_string()),
+    });
+}
+
+/// Drops the records answering for `delivery_id`, so the sender's next
+/// attempt at it is processed afresh instead of replaying the old answer.
+pub fn forget(delivery_id: &str) {
+    for record in store::list_json::<IdempotencyRecord>(&idempotency_dir()) {
+        if record.delivery_id == delivery_id {
+            store::remove_json(&idempotency_dir(), &record.key);
+        }
+    }
+}
+
+/// Drops records that have aged out of the dedupe window.
+pub fn prune() {
+    let window = window();
+    let now = Local::now();
+    for record in store::list_json::<IdempotencyRecord>(&idempotency_dir()) {
+        if now.signed_duration_since(record.recorded_at) >= window {
+            store::remove_json(&idempotency_dir(), &record.key);
+        }
+    }
+}
+
+fn save(record: IdempotencyRecord) {
+    if let Err(e) = store::write_json(&idempotency_dir(), &record.key, &record) {
+        log_msg(&format!("Failed to store idempotency key for delivery {}: {}", record.delivery_id, e), "⚠️");
+    }
+}
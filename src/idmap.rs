@@ -0,0 +1,129 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::PathBuf;
+
+use crate::handlers::Payload;
+use crate::log_msg;
+use crate::routing::Target;
+use crate::store::{self, data_dir};
+
+/// Links a Subcontractor Hub record to the JobNimbus record we created for it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdMapping {
+    pub sch_id: String,
+    /// The JobNimbus endpoint the record lives on.
+    #[serde(default)]
+    pub target: Target,
+    pub jnid: String,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    /// The delivery that last created or updated the JobNimbus record.
+    pub delivery_id: Option<String>,
+}
+
+// The same mapping is stored twice, keyed by each side, so both lookups are a
+// single file read.
+fn by_sch_dir() -> PathBuf {
+    data_dir().join("idmap").join("by_sch")
+}
+
+fn by_jnid_dir() -> PathBuf {
+    data_dir().join("idmap").join("by_jnid")
+}
+
+/// The Subcontractor Hub record id, read from `data` at `SCH_ID_FIELD` (default `id`).
+pub fn sch_record_id(payload: &Payload) -> Option<String> {
+    let field = env::var("SCH_ID_FIELD").unwrap_or_else(|_| "id".to_string());
+    id_at(payload, &field)
+}
+
+/// Reads a record id (string or number) from `data` at a dotted path.
+pub fn id_at(payload: &Payload, path: &str) -> Option<String> {
+    let mut value = payload.data.as_ref()?;
+    for segment in path.split('.') {
+        value = value.get(segment)?;
+    }
+    match value {
+        serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// Contacts are stored under the SCH id itself; other endpoints are prefixed
+// with theirs (`jobs:...`) so a project and a customer sharing an id don't
+// collide.
+fn storage_key(target: Target, sch_id: &str) -> String {
+    match target {
+        Target::Contacts => store::hashed_id(sch_id),
+        target => store::hashed_id(&format!("{}:{}", target, sch_id)),
+    }
+}
+
+/// The contact mapped to `sch_id`.
+pub fn by_sch_id(sch_id: &str) -> Option<IdMapping> {
+    by_sch_id_for(Target::Contacts, sch_id)
+}
+
+pub fn by_sch_id_for(target: Target, sch_id: &str) -> Option<IdMapping> {
+    store::read_json(&by_sch_dir(), &storage_key(target, sch_id))
+}
+
+pub fn by_jnid(jnid: &str) -> Option<IdMapping> {
+    store::read_json(&by_jnid_dir(), &store::hashed_id(jnid))
+}
+
+pub fn lookup_jnid(sch_id: &str) -> Option<String> {
+    lookup_jnid_for(Target::Contacts, sch_id)
+}
+
+pub fn lookup_jnid_for(target: Target, sch_id: &str) -> Option<String> {
+    by_sch_id_for(target, sch_id).map(|m| m.jnid)
+}
+
+/// All mappings, in no particular order.
+pub fn list() -> Vec<IdMapping> {
+    store::list_json(&by_sch_dir())
+}
+
+/// Stores (or refreshes) the link between `sch_id` and the contact `jnid`.
+pub fn record(sch_id: &str, jnid: &str, delivery_id: Option<&str>) {
+    record_for(Target::Contacts, sch_id, jnid, delivery_id)
+}
+
+/// Stores (or refreshes) the link between `sch_id` and the `target` record `jnid`.
+pub fn record_for(target: Target, sch_id: &str, jnid: &str, delivery_id: Option<&str>) {
+    let now = Local::now();
+    let previous = by_sch_id_for(target, sch_id);
+    if let Some(previous) = &previous {
+        if previous.jnid != jnid {
+            store::remove_json(&by_jnid_dir(), &store::hashed_id(&previous.jnid));
+        }
+    }
+
+    let mapping = IdMapping {
+        sch_id: sch_id.to_string(),
+        target,
+        jnid: jnid.to_string(),
+        created_at: previous.filter(|p| p.jnid == jnid).map_or(now, |p| p.created_at),
+        updated_at: now,
+        delivery_id: delivery_id.map(str::to_string),
+    };
+    let stored = store::write_json(&by_sch_dir(), &storage_key(target, sch_id), &mapping)
+        .and_then(|_| store::write_json(&by_jnid_dir(), &store::hashed_id(jnid), &mapping));
+    if let Err(e) = stored {
+        log_msg(&format!("Failed to store {} id mapping {} -> {}: {}", target, sch_id, jnid, e), "⚠️");
+    }
+}
+
+pub fn forget(sch_id: &str) {
+    forget_for(Target::Contacts, sch_id)
+}
+
+pub fn forget_for(target: Target, sch_id: &str) {
+    if let Some(mapping) = by_sch_id_for(target, sch_id) {
+        store::remove_json(&by_jnid_dir(), &store::hashed_id(&mapping.jnid));
+    }
+    store::remove_json(&by_sch_dir(), &storage_key(target, sch_id));
+}
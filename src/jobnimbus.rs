@@ -0,0 +1,159 @@
+use reqwest::{Client, Method};
+use std::env;
+use std::time::Duration;
+
+use crate::contacts;
+use crate::idmap;
+use crate::log_msg;
+use crate::rate_limit;
+use crate::queue::Delivery;
+use crate::retry::parse_retry_after;
+use crate::routing::{self, Target};
+
+pub const DEFAULT_BASE_URL: &str = "https://app.jobnimbus.com";
+pub const DEFAULT_API_PREFIX: &str = "/api1";
+
+/// Root every JobNimbus endpoint hangs off: `JOBNIMBUS_BASE_URL` (default
+/// `https://app.jobnimbus.com`) followed by `JOBNIMBUS_API_PREFIX` (default
+/// `/api1`). Point these at a sandbox account or a mock server for testing.
+pub fn api_base() -> String {
+    let base = env::var("JOBNIMBUS_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+    let prefix = env::var("JOBNIMBUS_API_PREFIX").unwrap_or_else(|_| DEFAULT_API_PREFIX.to_string());
+    let prefix = prefix.trim().trim_matches('/');
+    let base = base.trim().trim_end_matches('/');
+    if prefix.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}/{}", base, prefix)
+    }
+}
+
+/// What JobNimbus (or another sink) answered, whatever the status code.
+#[derive(Debug, Clone)]
+pub struct UpstreamResponse {
+    pub status: u16,
+    pub body: String,
+    pub retry_after: Option<Duration>,
+    /// Made up by our rate limiter, which didn't let the call through; says
+    /// nothing about JobNimbus itself.
+    pub limited: bool,
+}
+
+impl UpstreamResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Reads the status, `Retry-After` and full body of an answer.
+    pub async fn read(response: reqwest::Response) -> Result<Self, String> {
+        let status = response.status().as_u16();
+        let retry_after = response.headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+        Ok(UpstreamResponse { status, body, retry_after, limited: false })
+    }
+
+    /// A `503` for a call the rate limiter would have held for `wait`.
+    pub fn rate_limited(wait: Duration) -> Self {
+        UpstreamResponse {
+            status: 503,
+            body: "Job Nimbus rate limit reached".to_string(),
+            retry_after: Some(wait),
+            limited: true,
+        }
+    }
+
+    /// The `jnid` of the record JobNimbus created or updated, if it told us.
+    pub fn jnid(&self) -> Option<String> {
+        let parsed: serde_json::Value = serde_json::from_str(&self.body).ok()?;
+        parsed.get("jnid")?.as_str().map(str::to_string)
+    }
+}
+
+/// Sends a delivery to JobNimbus. An `Err` means we never got a complete
+/// answer (missing key, connection failure, truncated body), so the delivery
+/// is safe to try again.
+pub async fn forward(client: &Client, delivery: &Delivery) -> Result<UpstreamResponse, String> {
+    match delivery.target {
+        Target::Contacts if contacts::upsert_enabled() => contacts::upsert(client, delivery).await,
+        Target::Contacts => contacts::create(client, delivery).await,
+        target => {
+            let body: serde_json::Value = serde_json::from_str(&delivery.body).unwrap_or(serde_json::Value::Null);
+            let body = routing::shape(target, body, &delivery.payload).to_string();
+            match target {
+                Target::Jobs | Target::Tasks => write_record(client, target, delivery, &body).await,
+                _ => send(client, Method::POST, target.path(), Some(&body)).await,
+            }
+        }
+    }
+}
+
+/// Updates the job or task created for the delivery's SCH record, or creates
+/// one and remembers its jnid so later events for the record update it.
+async fn write_record(client: &Client, target: Target, delivery: &Delivery, body: &str) -> Result<UpstreamResponse, String> {
+    let sch_id = idmap::sch_record_id(&delivery.payload);
+
+    let mapped = sch_id.as_deref().and_then(|sch_id| idmap::lookup_jnid_for(target, sch_id).map(|jnid| (sch_id, jnid)));
+    if let Some((sch_id, jnid)) = mapped {
+        let response = send(client, Method::PUT, &format!("{}/{}", target.path(), jnid), Some(body)).await?;
+        // A mapping can outlive the record; forget it and create a new one.
+        if response.status != 404 {
+            if response.is_success() {
+                idmap::record_for(target, sch_id, &jnid, Some(&delivery.id));
+            }
+            return Ok(response);
+        }
+        log_msg(&format!("Mapped {} record {} no longer exists; dropping mapping for {}", target, jnid, sch_id), "⚠️");
+        idmap::forget_for(target, sch_id);
+    }
+
+    let response = send(client, Method::POST, target.path(), Some(body)).await?;
+    if response.is_success() {
+        if let (Some(sch_id), Some(jnid)) = (&sch_id, response.jnid()) {
+            idmap::record_for(target, sch_id, &jnid, Some(&delivery.id));
+        }
+    }
+    Ok(response)
+}
+
+/// Runs a JobNimbus contact search with an ElasticSearch-style filter.
+pub async fn search_contacts(client: &Client, filter: &serde_json::Value) -> Result<UpstreamResponse, String> {
+    let path = format!("/contacts?filter={}", urlencode(&filter.to_string()));
+    send(client, Method::GET, &path, None).await
+}
+
+/// Makes a single authenticated call to `api_base()` + `path`.
+pub async fn send(client: &Client, method: Method, path: &str, body: Option<&str>) -> Result<UpstreamResponse, String> {
+    let api_key = env::var("JOB_NIMBUS_API_KEY")
+        .map_err(|_| "JOB_NIMBUS_API_KEY not set in environment.".to_string())?;
+
+    // Calls are made from request handlers too, so waiting is bounded.
+    if let Err(wait) = rate_limit::acquire(rate_limit::max_wait()).await {
+        return Ok(UpstreamResponse::rate_limited(wait));
+    }
+    let mut request = client.request(method, format!("{}{}", api_base(), path))
+        .header("Authorization", format!("bearer {}", api_key));
+    if let Some(body) = body {
+        request = request
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+    }
+
+    let response = request.send()
+        .await
+        .map_err(|e| format!("HTTP request error: {}", e))?;
+    UpstreamResponse::read(response).await
+}
+
+fn urlencode(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
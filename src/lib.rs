@@ -29,4 +29,33 @@ pub fn log_msg(message: &str, emoji: &str) {
     println!("{} {}", emoji, message);
 }
 
+pub mod circuit_breaker;
+pub mod coalesce;
+pub mod config_file;
+pub mod contacts;
+pub mod dead_letter;
+pub mod delivery;
+pub mod field_map;
 pub mod handlers;
+pub mod history;
+pub mod http_client;
+pub mod idempotency;
+pub mod idmap;
+pub mod jobnimbus;
+pub mod metrics;
+pub mod ordering;
+pub mod plugin;
+pub mod preflight;
+pub mod queue;
+pub mod rate_limit;
+pub mod response;
+pub mod retry;
+pub mod routing;
+pub mod rules;
+pub mod schema;
+pub mod script;
+pub mod sink;
+pub mod source;
+pub mod store;
+pub mod transform;
+pub mod worker;
@@ -2,9 +2,14 @@ use actix_web::{App, HttpServer, HttpResponse, HttpRequest, web};
 use dotenv::dotenv;
 use std::env;
 use std::fs::create_dir_all;
-use sch2jn::log_msg;
+use sch2jn::{http_client, log_msg, queue, worker};
 use sch2jn::handlers::{
     index_handler, logs_handler, post_handler, run_tests_handler, clear_logs_handler, static_file_handler,
+    dead_letters_handler, dead_letter_handler, edit_dead_letter_handler, replay_dead_letter_handler,
+    discard_dead_letter_handler, mappings_handler, metrics_handler, field_mappings_handler,
+    reload_field_mappings_handler, delivery_status_handler, status_handler,
+    health_handler, rules_handler, reload_rules_handler, source_handler,
+    plugins_handler, schemas_handler, refresh_preflight_handler,
 };
 use std::io::Write;
 
@@ -19,6 +24,58 @@ async fn config_handler(_req: HttpRequest) -> HttpResponse {
         ("GUI_AUTH_REQUIRED", Some("false"), "Toggle GUI authentication", "boolean"),
         ("GUI_PASSWORD", None, "Password for GUI access", "string"),
         ("TEST_MODE", Some("false"), "Toggle test mode for simulated requests", "boolean"),
+        ("JOBNIMBUS_BASE_URL", Some("https://app.jobnimbus.com"), "Job Nimbus server to forward to (a sandbox or mock server for testing)", "string"),
+        ("JOBNIMBUS_API_PREFIX", Some("/api1"), "Path prefix of the Job Nimbus API on that server", "string"),
+        ("JOBNIMBUS_RATE_LIMIT", Some("5"), "Job Nimbus calls per second (0 disables the limiter)", "number"),
+        ("JOBNIMBUS_RATE_BURST", Some("10"), "Job Nimbus calls allowed back to back after a quiet spell", "number"),
+        ("JOBNIMBUS_RATE_MAX_WAIT_MS", Some("2000"), "Longest a delivery waits for the rate limiter before going back to the queue", "number"),
+        ("CIRCUIT_FAILURE_THRESHOLD", Some("5"), "Consecutive Job Nimbus failures that open the circuit breaker (0 disables)", "number"),
+        ("CIRCUIT_OPEN_SECS", Some("30"), "Seconds the circuit stays open before a probe request", "number"),
+        ("HTTP_CONNECT_TIMEOUT_SECS", Some("10"), "Seconds to wait for a connection to Job Nimbus", "number"),
+        ("HTTP_REQUEST_TIMEOUT_SECS", Some("30"), "Seconds before an outbound request is abandoned", "number"),
+        ("HTTP_POOL_MAX_IDLE", Some("10"), "Idle connections kept open per host", "number"),
+        ("HTTP_POOL_IDLE_TIMEOUT_SECS", Some("90"), "Seconds before an idle connection is closed", "number"),
+        ("HTTP_USER_AGENT", None, "User agent for outbound requests (default: sch2jn/<version>)", "string"),
+        ("OUTBOUND_PROXY", None, "Proxy URL for all outbound requests", "string"),
+        ("OUTBOUND_NO_PROXY", None, "Comma separated hosts that bypass OUTBOUND_PROXY", "string"),
+        ("HTTP_CA_BUNDLE", None, "PEM file of extra root certificates to trust", "string"),
+        ("DATA_DIR", Some("data"), "Directory for the delivery queue and other persisted state", "string"),
+        ("ACCEPT_MODE", Some("sync"), "sync waits for Job Nimbus before answering; async answers 202 and forwards in the background", "string"),
+        ("COALESCE_WINDOW_SECS", Some("0"), "Seconds to hold the first update to a record so later updates merge into it (0 disables)", "number"),
+        ("ORDERING_TIMESTAMP_FIELD", Some("updated_at"), "Path of the event timestamp used to detect out-of-order updates", "string"),
+        ("STALE_EVENT_POLICY", Some("flag"), "flag sends out-of-order updates anyway and logs them; drop skips them", "string"),
+        ("DELIVERY_HISTORY_DAYS", Some("7"), "Days finished deliveries stay available from /deliveries/{id}", "number"),
+        ("QUEUE_POLL_INTERVAL_SECS", Some("5"), "Seconds between delivery queue scans", "number"),
+        ("RETRY_MAX_ATTEMPTS", Some("5"), "Delivery attempts before giving up", "number"),
+        ("RETRY_BASE_DELAY_MS", Some("1000"), "Initial retry delay, doubled after each failed attempt", "number"),
+        ("RETRY_MAX_DELAY_MS", Some("300000"), "Upper bound for the retry delay", "number"),
+        ("RETRY_JITTER", Some("0.2"), "Random fraction added to or removed from each retry delay", "number"),
+        ("EVENT_ROUTES", None, "Event to endpoint routes, e.g. project.*=jobs,note.*=activities (default: everything to contacts)", "string"),
+        ("EVENT_ALLOWLIST", None, "Event globs to forward, e.g. customer.*,project.* (default: all events)", "string"),
+        ("EVENT_DENYLIST", None, "Event globs acknowledged as ignored without forwarding, e.g. *.viewed", "string"),
+        ("SCH_CONTACT_REF_FIELD", Some("customer_id"), "Path of the SCH customer id used to link jobs, tasks and activities to a contact", "string"),
+        ("MAPPINGS_FILE", Some("config/mappings.json"), "Field mapping file applied to inbound data before forwarding", "string"),
+        ("SCRIPTS_DIR", Some("config/scripts"), "Directory holding the Rhai scripts named by field mappings", "string"),
+        ("SCRIPT_MAX_OPERATIONS", Some("100000"), "Operations a mapping script may run before it is stopped", "number"),
+        ("SCRIPT_TIMEOUT_MS", Some("1000"), "Wall-clock limit for one mapping script run", "number"),
+        ("SCHEMAS_DIR", Some("config/schemas"), "Directory of per-event JSON Schemas inbound data is validated against", "string"),
+        ("SCHEMA_MODE", Some("enforce"), "enforce rejects payloads that violate their schema, warn only logs them", "string"),
+        ("PLUGINS_DIR", Some("config/plugins"), "Directory of WebAssembly plugins run over every inbound event", "string"),
+        ("PLUGIN_FUEL", Some("10000000"), "Fuel (roughly instructions) a plugin may use per call", "number"),
+        ("PLUGIN_MAX_MEMORY_MB", Some("16"), "Linear memory a plugin may grow to", "number"),
+        ("SOURCES_FILE", Some("config/sources.json"), "Named inbound sources accepted on /in/{source} (sch is built in)", "string"),
+        ("SOURCE_MAX_BATCH", Some("100"), "Most events accepted in one batch posted to a source", "number"),
+        ("SINKS_FILE", Some("config/sinks.json"), "Destinations every delivery is fanned out to (default: Job Nimbus only)", "string"),
+        ("RULES_FILE", Some("config/rules.json"), "Routing rules that drop, route, tag or hold inbound events", "string"),
+        ("IDEMPOTENCY_HEADER", Some("Idempotency-Key"), "Request header carrying the webhook delivery id", "string"),
+        ("IDEMPOTENCY_WINDOW_SECS", Some("86400"), "How long duplicate webhooks are answered from the idempotency store (0 disables)", "number"),
+        ("UPSERT_MODE", Some("false"), "Update matching Job Nimbus contacts instead of always creating new ones", "boolean"),
+        ("CONTACT_MATCH_KEYS", Some("external_id,email,phone"), "Contact match keys for upsert mode, in precedence order", "string"),
+        ("SCH_ID_FIELD", Some("id"), "Path of the Subcontractor Hub record id inside data", "string"),
+        ("CONTACT_PREFLIGHT", Some("false"), "Check contacts against JobNimbus requirements and record types before sending", "boolean"),
+        ("CONTACT_REQUIRED_FIELDS", Some("display_name,record_type_name,status_name"), "Fields preflight checks require on new contacts", "string"),
+        ("JOBNIMBUS_SETTINGS_TTL_SECS", Some("3600"), "How long fetched JobNimbus record types and statuses are cached", "number"),
+        ("UPSTREAM_STATUS_MAP", Some("2xx=200,401=502,403=502,429=503,4xx=422,5xx=502"), "How Job Nimbus statuses map to the status we answer with (first match wins)", "string"),
     ];
 
     let sensitive_keys = ["SUBCONTRACTOR_API_KEY", "JOB_NIMBUS_API_KEY", "GUI_PASSWORD"];
@@ -102,6 +159,16 @@ async fn main() -> std::io::Result<()> {
     check_single_instance();
     log_msg("Starting server", "🚀");
 
+    // One pooled client for every outbound call, shared by handlers and the worker.
+    let client = match http_client::build() {
+        Ok(client) => web::Data::new(client),
+        Err(e) => {
+            log_msg(&e, "❌");
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let port_env = env::var("PORT").unwrap_or_else(|_| "8787".to_string());
     let mut port: u16 = port_env.parse().unwrap_or(8787);
     let mut bind_success = false;
@@ -111,15 +178,35 @@ async fn main() -> std::io::Result<()> {
     for _ in 0..5 {
         let bind_addr = format!("0.0.0.0:{}", port);
         // Store the result of `.bind()` in a variable.
-        let bind_result = HttpServer::new(|| {
+        let app_client = client.clone();
+        let bind_result = HttpServer::new(move || {
             App::new()
+                .app_data(app_client.clone())
                 .route("/", web::get().to(index_handler))
                 .route("/logs", web::get().to(logs_handler))
                 .route("/run_tests", web::post().to(run_tests_handler))
                 .route("/clear_logs", web::post().to(clear_logs_handler))
                 .route("/config", web::get().to(config_handler))
+                .route("/metrics", web::get().to(metrics_handler))
+                .route("/status", web::get().to(status_handler))
+                .route("/health", web::get().to(health_handler))
+                .route("/mappings", web::get().to(mappings_handler))
+                .route("/field_mappings", web::get().to(field_mappings_handler))
+                .route("/field_mappings/reload", web::post().to(reload_field_mappings_handler))
+                .route("/rules", web::get().to(rules_handler))
+                .route("/rules/reload", web::post().to(reload_rules_handler))
+                .route("/plugins", web::get().to(plugins_handler))
+                .route("/schemas", web::get().to(schemas_handler))
+                .route("/preflight/refresh", web::post().to(refresh_preflight_handler))
+                .route("/deliveries/{id}", web::get().to(delivery_status_handler))
+                .route("/dead_letters", web::get().to(dead_letters_handler))
+                .route("/dead_letters/{id}", web::get().to(dead_letter_handler))
+                .route("/dead_letters/{id}", web::put().to(edit_dead_letter_handler))
+                .route("/dead_letters/{id}", web::delete().to(discard_dead_letter_handler))
+                .route("/dead_letters/{id}/replay", web::post().to(replay_dead_letter_handler))
                 .route("/static/{filename:.*}", web::get().to(static_file_handler))
                 .route("/", web::post().to(post_handler))
+                .route("/in/{source}", web::post().to(source_handler))
         })
         .bind(&bind_addr);
         
@@ -149,6 +236,13 @@ async fn main() -> std::io::Result<()> {
     let server = server_builder.unwrap().run();
     let srv_handle = server.handle();
 
+    // Resume anything left in the delivery queue and keep draining it.
+    let pending = queue::pending().len();
+    if pending > 0 {
+        log_msg(&format!("Resuming {} pending deliveries from queue", pending), "🔄");
+    }
+    actix_web::rt::spawn(worker::run(client.get_ref().clone()));
+
     // Spawn a task to listen for termination signals (only on unix).
     #[cfg(unix)]
     tokio::spawn(async move {
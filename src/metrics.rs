@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::circuit_breaker::{self, CircuitState};
+use crate::rate_limit;
+
+static COUNTERS: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+// Help text for every counter we expose, in Prometheus exposition order.
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    ("sch2jn_payloads_received_total", "Payloads accepted on the inbound endpoint"),
+    ("sch2jn_deliveries_succeeded_total", "Deliveries accepted by Job Nimbus"),
+    ("sch2jn_deliveries_failed_total", "Deliveries moved to the dead-letter store"),
+    ("sch2jn_delivery_retries_total", "Delivery attempts rescheduled after a transient failure"),
+    ("sch2jn_events_ignored_total", "Payloads acknowledged without forwarding because of the event allow/deny lists"),
+    ("sch2jn_dedupe_hits_total", "Redelivered webhooks answered from the idempotency store"),
+    ("sch2jn_rate_limited_total", "Deliveries deferred to the queue by the outbound rate limiter"),
+    ("sch2jn_stale_events_total", "Deliveries older than an update already sent for the same record"),
+    ("sch2jn_circuit_short_circuits_total", "Deliveries queued without calling Job Nimbus while the circuit was open"),
+    ("sch2jn_rule_drops_total", "Payloads dropped by a routing rule"),
+    ("sch2jn_rule_holds_total", "Payloads held for review by a routing rule"),
+    ("sch2jn_script_drops_total", "Payloads dropped by a mapping script"),
+    ("sch2jn_script_errors_total", "Payloads that failed because their mapping script errored"),
+    ("sch2jn_schema_rejections_total", "Payloads rejected for violating their event's JSON Schema"),
+    ("sch2jn_schema_warnings_total", "Payloads forwarded despite violating a JSON Schema in warn mode"),
+    ("sch2jn_schema_errors_total", "Payloads whose event's JSON Schema failed to load"),
+    ("sch2jn_preflight_rejections_total", "Contacts rejected before sending because JobNimbus would refuse them"),
+    ("sch2jn_plugin_filtered_total", "Payloads filtered out by a WebAssembly plugin"),
+    ("sch2jn_plugin_errors_total", "Payloads that failed because a WebAssembly plugin errored"),
+];
+
+pub fn incr(name: &'static str) {
+    *COUNTERS.lock().unwrap().entry(name).or_insert(0) += 1;
+}
+
+pub fn get(name: &str) -> u64 {
+    COUNTERS.lock().unwrap().get(name).copied().unwrap_or(0)
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Renders all counters, followed by gauges read from live state, in the
+/// Prometheus text format.
+pub fn render() -> String {
+    let counters = COUNTERS.lock().unwrap();
+    let mut out = String::new();
+    for (name, help) in DESCRIPTIONS {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "{} {}", name, counters.get(name).copied().unwrap_or(0));
+    }
+    drop(counters);
+
+    let limiter = rate_limit::status();
+    write_gauge(&mut out, "sch2jn_rate_limit_tokens", "Tokens left in the Job Nimbus rate limit bucket", limiter.tokens);
+    write_gauge(&mut out, "sch2jn_rate_limit_waiting", "Job Nimbus calls waiting for a rate limit token", limiter.waiting as f64);
+    let circuit = match circuit_breaker::status().state {
+        CircuitState::Closed => 0.0,
+        CircuitState::HalfOpen => 1.0,
+        CircuitState::Open => 2.0,
+    };
+    write_gauge(&mut out, "sch2jn_circuit_state", "Job Nimbus circuit breaker (0 closed, 1 half-open, 2 open)", circuit);
+    out
+}
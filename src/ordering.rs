@@ -0,0 +1,142 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::field_map::resolve;
+use crate::handlers::Payload;
+use crate::idmap;
+use crate::log_msg;
+use crate::queue::{self, Delivery};
+use crate::store::{self, data_dir};
+
+type KeyLock = Arc<tokio::sync::Mutex<()>>;
+
+// One lock per record currently being written; entries are dropped again
+// once nobody holds or waits for them.
+static LOCKS: Mutex<Option<HashMap<String, KeyLock>>> = Mutex::new(None);
+
+/// What to do with an event older than the last one delivered for its record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StalePolicy {
+    /// Send it anyway, but log and count it.
+    Flag,
+    /// Don't send it.
+    Drop,
+}
+
+/// The newest event timestamp delivered for a record.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Watermark {
+    pub key: String,
+    pub event_time: DateTime<Utc>,
+    pub delivery_id: String,
+}
+
+fn watermark_dir() -> PathBuf {
+    data_dir().join("ordering")
+}
+
+/// `STALE_EVENT_POLICY`: `flag` (default) or `drop`.
+pub fn stale_policy() -> StalePolicy {
+    match env::var("STALE_EVENT_POLICY").unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+        "drop" => StalePolicy::Drop,
+        _ => StalePolicy::Flag,
+    }
+}
+
+/// The record a delivery writes to: its endpoint plus the SCH record id, or
+/// the contact's email when there is no id.
+pub fn record_key(delivery: &Delivery) -> Option<String> {
+    let identity = idmap::sch_record_id(&delivery.payload).or_else(|| {
+        let email = delivery.payload.data.as_ref()?.get("email")?.as_str()?.trim().to_ascii_lowercase();
+        (!email.is_empty()).then(|| format!("email:{}", email))
+    })?;
+    Some(format!("{}:{}", delivery.target, identity))
+}
+
+/// Waits until no other delivery for `key` is being sent, then holds the
+/// record until the returned guard is dropped.
+pub async fn lock(key: &str) -> KeyGuard {
+    let lock = LOCKS.lock().unwrap()
+        .get_or_insert_with(HashMap::new)
+        .entry(key.to_string())
+        .or_default()
+        .clone();
+    KeyGuard { key: key.to_string(), guard: Some(lock.lock_owned().await) }
+}
+
+pub struct KeyGuard {
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        self.guard.take();
+        let mut locks = LOCKS.lock().unwrap();
+        if let Some(map) = locks.as_mut() {
+            // Only the map itself still refers to it: nobody holds or awaits it.
+            if map.get(&self.key).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+                map.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// An older delivery for the same record that is still queued. Queue ids sort
+/// in arrival order, so anything with a smaller id arrived first.
+pub fn earlier_pending(delivery: &Delivery, key: &str) -> Option<Delivery> {
+    queue::pending_for(key)
+        .into_iter()
+        .take_while(|(id, _)| *id < delivery.id)
+        .find_map(|(id, _)| queue::get(&id))
+}
+
+/// When the event happened, read from `ORDERING_TIMESTAMP_FIELD` (default
+/// `updated_at` in `data`; `$.` paths read the whole payload). Accepts
+/// RFC 3339 strings and unix timestamps in seconds or milliseconds.
+pub fn event_time(payload: &Payload) -> Option<DateTime<Utc>> {
+    let field = env::var("ORDERING_TIMESTAMP_FIELD").unwrap_or_else(|_| "updated_at".to_string());
+    let value = match field.strip_prefix("$.") {
+        Some(path) => resolve(&serde_json::to_value(payload).ok()?, path)?,
+        None => resolve(payload.data.as_ref()?, &field)?,
+    };
+    let unix = |n: i64| {
+        // Anything past the year 2286 in seconds is really milliseconds.
+        if n.abs() >= 10_000_000_000 {
+            Utc.timestamp_millis_opt(n).single()
+        } else {
+            Utc.timestamp_opt(n, 0).single()
+        }
+    };
+    match value {
+        Value::Number(n) => unix(n.as_i64()?),
+        Value::String(s) => DateTime::parse_from_rfc3339(s.trim())
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok()
+            .or_else(|| unix(s.trim().parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// The watermark that makes an event at `event_time` stale, if any.
+pub fn newer_delivered(key: &str, event_time: DateTime<Utc>) -> Option<Watermark> {
+    let mark: Watermark = store::read_json(&watermark_dir(), &store::hashed_id(key))?;
+    (mark.event_time > event_time).then_some(mark)
+}
+
+/// Remembers the newest event delivered for a record.
+pub fn record_delivered(key: &str, event_time: DateTime<Utc>, delivery_id: &str) {
+    if newer_delivered(key, event_time).is_some() {
+        return;
+    }
+    let mark = Watermark { key: key.to_string(), event_time, delivery_id: delivery_id.to_string() };
+    if let Err(e) = store::write_json(&watermark_dir(), &store::hashed_id(key), &mark) {
+        log_msg(&format!("Failed to store ordering watermark for {}: {}", key, e), "⚠️");
+    }
+}
@@ -0,0 +1,314 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, metadata};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use wasmi::{Caller, Config, Engine, Extern, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::handlers::Payload;
+use crate::log_msg;
+
+/// How a plugin run ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginOutcome {
+    /// The plugin rewrote the event.
+    Transformed,
+    /// The plugin let the event through as it was.
+    Passed,
+    /// The plugin's `filter` rejected the event.
+    Filtered,
+    Failed,
+}
+
+/// One plugin's part in handling an event, kept with the delivery.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PluginRun {
+    pub plugin: String,
+    pub outcome: PluginOutcome,
+    pub elapsed_ms: f64,
+    pub fuel_used: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What the plugins decided about an event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Carry on with the (possibly transformed) event.
+    Keep,
+    /// This plugin filtered the event out.
+    Filtered(String),
+    /// A plugin failed, with this error.
+    Failed(String),
+}
+
+/// A plugin found in the plugins directory, as reported by `GET /plugins`.
+#[derive(Serialize, Debug, Clone)]
+pub struct PluginInfo {
+    pub name: String,
+    pub exports: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Loaded {
+    modified: Option<SystemTime>,
+    module: Result<Arc<Module>, String>,
+}
+
+struct Host {
+    engine: Engine,
+    plugins: BTreeMap<String, Loaded>,
+}
+
+// Compiled plugins by file name, recompiled when the file changes.
+static HOST: Mutex<Option<Host>> = Mutex::new(None);
+
+/// State each plugin instance runs with.
+struct PluginState {
+    plugin: String,
+    limits: StoreLimits,
+}
+
+/// Directory plugins are loaded from (`PLUGINS_DIR`, default `config/plugins`).
+pub fn plugins_dir() -> PathBuf {
+    PathBuf::from(env::var("PLUGINS_DIR").unwrap_or_else(|_| "config/plugins".to_string()))
+}
+
+/// Instructions (roughly) a plugin may run per call (`PLUGIN_FUEL`, default 10000000).
+fn fuel() -> u64 {
+    env::var("PLUGIN_FUEL")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(10_000_000)
+}
+
+/// Linear memory a plugin may grow to (`PLUGIN_MAX_MEMORY_MB`, default 16).
+fn max_memory() -> usize {
+    let mb: usize = env::var("PLUGIN_MAX_MEMORY_MB")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(16);
+    mb << 20
+}
+
+/// The `*.wasm` files in the plugins directory, in name order. Files that
+/// appeared or changed since the last call are compiled, removed ones dropped.
+fn refresh(host: &mut Host) {
+    let dir = plugins_dir();
+    let mut found = BTreeMap::new();
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "wasm") {
+                if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                    found.insert(name.to_string(), path);
+                }
+            }
+        }
+    }
+
+    host.plugins.retain(|name, _| found.contains_key(name));
+    for (name, path) in found {
+        let modified = metadata(&path).and_then(|m| m.modified()).ok();
+        if host.plugins.get(&name).is_some_and(|loaded| loaded.modified == modified) {
+            continue;
+        }
+        let module = compile(&host.engine, &path);
+        match &module {
+            Ok(_) => log_msg(&format!("Loaded plugin {}", name), "🧩"),
+            Err(e) => log_msg(&format!("Failed to load plugin {}: {}", name, e), "❌"),
+        }
+        host.plugins.insert(name, Loaded { modified, module });
+    }
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<Arc<Module>, String> {
+    let wasm = fs::read(path).map_err(|e| e.to_string())?;
+    Module::new(engine, &wasm).map(Arc::new).map_err(|e| e.to_string())
+}
+
+fn with_host<R>(f: impl FnOnce(&mut Host) -> R) -> R {
+    let mut host = HOST.lock().unwrap();
+    let host = host.get_or_insert_with(|| {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Host { engine: Engine::new(&config), plugins: BTreeMap::new() }
+    });
+    refresh(host);
+    f(host)
+}
+
+/// The plugins currently in the plugins directory and what they export.
+pub fn loaded() -> Vec<PluginInfo> {
+    with_host(|host| {
+        host.plugins
+            .iter()
+            .map(|(name, loaded)| match &loaded.module {
+                Ok(module) => PluginInfo {
+                    name: name.clone(),
+                    exports: module.exports().map(|export| export.name().to_string()).collect(),
+                    error: None,
+                },
+                Err(e) => PluginInfo { name: name.clone(), exports: Vec::new(), error: Some(e.clone()) },
+            })
+            .collect()
+    })
+}
+
+/// Runs every plugin over an event, in file name order, replacing it with
+/// whatever each `transform` returns. The first filter or failure stops the
+/// chain. Returns the verdict and a report of every plugin that ran.
+pub fn run(payload: &mut Payload) -> (Verdict, Vec<PluginRun>) {
+    // Plugins run without holding the lock.
+    let (engine, plugins) = with_host(|host| {
+        let plugins: Vec<(String, Result<Arc<Module>, String>)> = host.plugins
+            .iter()
+            .map(|(name, loaded)| (name.clone(), loaded.module.clone()))
+            .collect();
+        (host.engine.clone(), plugins)
+    });
+
+    let mut runs = Vec::new();
+    for (name, module) in plugins {
+        let started = Instant::now();
+        let mut fuel_used = 0;
+        let result = module.and_then(|module| call(&engine, &name, &module, payload, &mut fuel_used));
+        let mut run = PluginRun {
+            plugin: name.clone(),
+            outcome: PluginOutcome::Passed,
+            elapsed_ms: (started.elapsed().as_secs_f64() * 1_000_000.0).round() / 1_000.0,
+            fuel_used,
+            error: None,
+        };
+        match result {
+            Ok(Step::Pass) => runs.push(run),
+            Ok(Step::Transform(transformed)) => {
+                run.outcome = PluginOutcome::Transformed;
+                runs.push(run);
+                *payload = transformed;
+            }
+            Ok(Step::Filter) => {
+                run.outcome = PluginOutcome::Filtered;
+                runs.push(run);
+                return (Verdict::Filtered(name), runs);
+            }
+            Err(e) => {
+                let error = format!("Plugin {}: {}", name, e);
+                run.outcome = PluginOutcome::Failed;
+                run.error = Some(error.clone());
+                runs.push(run);
+                return (Verdict::Failed(error), runs);
+            }
+        }
+    }
+    (Verdict::Keep, runs)
+}
+
+enum Step {
+    Pass,
+    Transform(Payload),
+    Filter,
+}
+
+/// Runs one plugin over an event in a fresh instance, so plugins keep no
+/// state between events.
+fn call(engine: &Engine, name: &str, module: &Module, payload: &Payload, fuel_used: &mut u64) -> Result<Step, String> {
+    let mut store = new_store(engine, name)?;
+    let result = call_in(&mut store, module, payload);
+    *fuel_used = fuel().saturating_sub(store.get_fuel().unwrap_or_default());
+    result
+}
+
+fn new_store(engine: &Engine, name: &str) -> Result<Store<PluginState>, String> {
+    let state = PluginState {
+        plugin: name.to_string(),
+        limits: StoreLimitsBuilder::new().memory_size(max_memory()).instances(1).build(),
+    };
+    let mut store = Store::new(engine, state);
+    store.limiter(|state| &mut state.limits);
+    store.set_fuel(fuel()).map_err(|e| e.to_string())?;
+    Ok(store)
+}
+
+fn instantiate(store: &mut Store<PluginState>, module: &Module) -> Result<Instance, String> {
+    let mut linker = Linker::<PluginState>::new(store.engine());
+    linker
+        .func_wrap("env", "log", |caller: Caller<'_, PluginState>, ptr: i32, len: i32| {
+            let Some(Extern::Memory(memory)) = caller.get_export("memory") else { return };
+            if let Some(text) = read(memory.data(&caller), ptr as u32, len as u32) {
+                log_msg(&format!("Plugin {}: {}", caller.data().plugin, String::from_utf8_lossy(text)), "🧩");
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    linker
+        .instantiate(&mut *store, module)
+        .and_then(|pre| pre.start(&mut *store))
+        .map_err(|e| e.to_string())
+}
+
+fn call_in(store: &mut Store<PluginState>, module: &Module, payload: &Payload) -> Result<Step, String> {
+    let instance = instantiate(store, module)?;
+
+    let event = serde_json::to_vec(payload).map_err(|e| e.to_string())?;
+    if instance.get_func(&*store, "filter").is_some() {
+        let filter = instance.get_typed_func::<(i32, i32), i32>(&*store, "filter").map_err(|e| e.to_string())?;
+        let (ptr, len) = pass_in(store, &instance, &event)?;
+        if filter.call(&mut *store, (ptr, len)).map_err(|e| e.to_string())? == 0 {
+            return Ok(Step::Filter);
+        }
+    }
+    if instance.get_func(&*store, "transform").is_none() {
+        return Ok(Step::Pass);
+    }
+
+    let transform = instance.get_typed_func::<(i32, i32), i64>(&*store, "transform").map_err(|e| e.to_string())?;
+    let (ptr, len) = pass_in(store, &instance, &event)?;
+    let packed = transform.call(&mut *store, (ptr, len)).map_err(|e| e.to_string())? as u64;
+    if packed == 0 {
+        return Ok(Step::Pass);
+    }
+    let memory = instance.get_memory(&*store, "memory").ok_or("plugin exports no memory")?;
+    let out = read(memory.data(&*store), (packed >> 32) as u32, packed as u32)
+        .ok_or("transform returned a result outside its memory")?;
+    let transformed: Payload =
+        serde_json::from_slice(out).map_err(|e| format!("transform returned invalid JSON: {}", e))?;
+    if transformed.data.is_none() {
+        return Err("transform returned an event without data".to_string());
+    }
+    Ok(Step::Transform(transformed))
+}
+
+/// Hands `message` to the `deliver` export of the plugin file `name`, in a
+/// fresh instance, and returns the status code it answers with.
+pub fn deliver(name: &str, message: &[u8]) -> Result<i32, String> {
+    let (engine, module) = with_host(|host| {
+        let module = host.plugins.get(name).map(|loaded| loaded.module.clone());
+        (host.engine.clone(), module)
+    });
+    let module = module.ok_or_else(|| format!("no plugin {} in {}", name, plugins_dir().display()))??;
+
+    let mut store = new_store(&engine, name)?;
+    let instance = instantiate(&mut store, &module)?;
+    let deliver = instance.get_typed_func::<(i32, i32), i32>(&store, "deliver").map_err(|e| format!("deliver: {}", e))?;
+    let (ptr, len) = pass_in(&mut store, &instance, message)?;
+    deliver.call(&mut store, (ptr, len)).map_err(|e| e.to_string())
+}
+
+/// Copies `bytes` into memory the plugin allocated with its `alloc` export.
+fn pass_in(store: &mut Store<PluginState>, instance: &Instance, bytes: &[u8]) -> Result<(i32, i32), String> {
+    let alloc = instance.get_typed_func::<i32, i32>(&*store, "alloc").map_err(|e| format!("alloc: {}", e))?;
+    let memory = instance.get_memory(&*store, "memory").ok_or("plugin exports no memory")?;
+    let len = i32::try_from(bytes.len()).map_err(|_| "event too large")?;
+    let ptr = alloc.call(&mut *store, len).map_err(|e| e.to_string())?;
+    memory.write(&mut *store, ptr as u32 as usize, bytes).map_err(|e| format!("alloc returned unusable memory: {}", e))?;
+    Ok((ptr, len))
+}
+
+fn read(memory: &[u8], ptr: u32, len: u32) -> Option<&[u8]> {
+    let start = ptr as usize;
+    memory.get(start..start.checked_add(len as usize)?)
+}
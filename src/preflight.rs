@@ -0,0 +1,219 @@
+use reqwest::{Client, Method};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::circuit_breaker;
+use crate::contacts;
+use crate::field_map::FieldError;
+use crate::jobnimbus;
+use crate::log_msg;
+
+/// Contact record types (workflows) and the statuses each allows, as set up
+/// in the JobNimbus account.
+pub type Workflows = BTreeMap<String, Vec<String>>;
+
+struct Cached {
+    fetched_at: Instant,
+    workflows: Workflows,
+}
+
+static SETTINGS: Mutex<Option<Cached>> = Mutex::new(None);
+static LAST_FAILURE: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Settings younger than this aren't fetched again just because a value is
+/// unknown, and a failed fetch isn't retried sooner.
+const REFETCH_AFTER: Duration = Duration::from_secs(60);
+
+/// Whether contacts are checked before sending (`CONTACT_PREFLIGHT`, default
+/// false). Never in test mode, where nothing reaches JobNimbus.
+pub fn enabled() -> bool {
+    env::var("CONTACT_PREFLIGHT").map(|v| v.trim() == "true").unwrap_or(false)
+        && env::var("TEST_MODE").unwrap_or_default() != "true"
+}
+
+/// Fields every new contact needs (`CONTACT_REQUIRED_FIELDS`, default
+/// `display_name,record_type_name,status_name`).
+fn required_fields() -> Vec<String> {
+    env::var("CONTACT_REQUIRED_FIELDS")
+        .unwrap_or_else(|_| "display_name,record_type_name,status_name".to_string())
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+/// How long fetched account settings are used (`JOBNIMBUS_SETTINGS_TTL_SECS`, default 3600).
+fn settings_ttl() -> Duration {
+    let secs = env::var("JOBNIMBUS_SETTINGS_TTL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(3600);
+    Duration::from_secs(secs)
+}
+
+/// Reads contact workflows out of a `GET /account/settings` answer, leaving
+/// out inactive workflows and statuses. `None` when there are none to be
+/// found: every account has at least one, so that means the answer isn't
+/// shaped the way we expect and the settings are as good as unknown.
+pub fn parse_workflows(settings: &Value) -> Option<Workflows> {
+    let active = |item: &Value| item.get("is_active").and_then(Value::as_bool).unwrap_or(true);
+    let mut workflows = Workflows::new();
+    for workflow in settings.get("workflows").and_then(Value::as_array).into_iter().flatten() {
+        let object_type = workflow.get("object_type").and_then(Value::as_str).unwrap_or("contact");
+        let Some(name) = workflow.get("name").and_then(Value::as_str) else { continue };
+        if object_type != "contact" || !active(workflow) {
+            continue;
+        }
+        let statuses = workflow.get("status")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|status| active(status))
+            .filter_map(|status| status.get("name").and_then(Value::as_str).map(str::to_string))
+            .collect();
+        workflows.insert(name.to_string(), statuses);
+    }
+    (!workflows.is_empty()).then_some(workflows)
+}
+
+async fn fetch(client: &Client) -> Result<Workflows, String> {
+    // While JobNimbus is down, checks make do with the settings we have.
+    if !circuit_breaker::allow() {
+        return Err("Job Nimbus circuit breaker open".to_string());
+    }
+    let result = jobnimbus::send(client, Method::GET, "/account/settings", None).await;
+    match &result {
+        Ok(response) if response.limited => {}
+        Ok(response) if response.status < 500 => circuit_breaker::record_success(),
+        _ => circuit_breaker::record_failure(),
+    }
+    let response = result?;
+    if !response.is_success() {
+        return Err(format!("JobNimbus answered HTTP {}", response.status));
+    }
+    let settings: Value = serde_json::from_str(&response.body).map_err(|e| format!("Invalid settings: {}", e))?;
+    parse_workflows(&settings).ok_or_else(|| "No active contact workflows in the account settings".to_string())
+}
+
+/// The account's contact workflows, fetched when the cache is older than
+/// `max_age`. If JobNimbus can't be asked, the last fetched settings are
+/// used; `None` when there are none.
+async fn workflows(client: &Client, max_age: Duration) -> Option<(Workflows, Instant)> {
+    let cached = SETTINGS.lock().unwrap()
+        .as_ref()
+        .map(|cached| (cached.workflows.clone(), cached.fetched_at));
+    if let Some((workflows, fetched_at)) = &cached {
+        if fetched_at.elapsed() < max_age {
+            return Some((workflows.clone(), *fetched_at));
+        }
+    }
+    if LAST_FAILURE.lock().unwrap().is_some_and(|at| at.elapsed() < REFETCH_AFTER) {
+        return cached;
+    }
+
+    match refresh(client).await {
+        Ok(workflows) => Some((workflows, Instant::now())),
+        Err(e) => {
+            log_msg(&format!("Couldn't fetch JobNimbus account settings for preflight checks: {}", e), "⚠️");
+            cached
+        }
+    }
+}
+
+/// Fetches the account settings now, replacing the cached ones.
+pub async fn refresh(client: &Client) -> Result<Workflows, String> {
+    match fetch(client).await {
+        Ok(workflows) => {
+            log_msg(&format!("Fetched {} JobNimbus contact workflows for preflight checks", workflows.len()), "🛫");
+            *SETTINGS.lock().unwrap() = Some(Cached { fetched_at: Instant::now(), workflows: workflows.clone() });
+            *LAST_FAILURE.lock().unwrap() = None;
+            Ok(workflows)
+        }
+        Err(e) => {
+            *LAST_FAILURE.lock().unwrap() = Some(Instant::now());
+            Err(e)
+        }
+    }
+}
+
+/// Checks an outbound contact against JobNimbus' requirements: the required
+/// fields (unless upserting, when the contact may already exist), and that
+/// `record_type_name` and `status_name` name a workflow and one of its
+/// statuses. When the account settings can't be fetched only the required
+/// fields are checked.
+pub async fn check_contact(client: &Client, contact: &Value) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let text = |field: &str| contact.get(field).and_then(Value::as_str).map(str::trim).filter(|v| !v.is_empty());
+
+    if !contacts::upsert_enabled() {
+        for field in required_fields() {
+            let present = match contact.get(&field) {
+                None | Some(Value::Null) => false,
+                Some(Value::String(value)) => !value.trim().is_empty(),
+                Some(_) => true,
+            };
+            if !present {
+                errors.push(FieldError { field, error: "JobNimbus requires this field on new contacts".to_string() });
+            }
+        }
+    }
+
+    let record_type = text("record_type_name");
+    let status = text("status_name");
+    if record_type.is_some() || status.is_some() {
+        let mut settings = workflows(client, settings_ttl()).await;
+        // A record type or status added in JobNimbus since the last fetch
+        // shouldn't be rejected, so look again before failing.
+        if settings.as_ref().is_some_and(|(workflows, fetched_at)| {
+            !value_problems(workflows, record_type, status).is_empty() && fetched_at.elapsed() >= REFETCH_AFTER
+        }) {
+            settings = workflows(client, Duration::ZERO).await;
+        }
+        if let Some((workflows, _)) = settings {
+            errors.extend(value_problems(&workflows, record_type, status));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn value_problems(workflows: &Workflows, record_type: Option<&str>, status: Option<&str>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let Some(record_type) = record_type else {
+        // Without a record type the status can only be checked against all of them.
+        if let Some(status) = status.filter(|s| !workflows.values().flatten().any(|known| known == s)) {
+            let all: BTreeSet<&String> = workflows.values().flatten().collect();
+            errors.push(unknown("status_name", "a status in JobNimbus", status, all.into_iter().collect()));
+        }
+        return errors;
+    };
+
+    let Some(statuses) = workflows.get(record_type) else {
+        errors.push(unknown("record_type_name", "a record type in JobNimbus", record_type, workflows.keys().collect()));
+        return errors;
+    };
+    if let Some(status) = status.filter(|s| !statuses.iter().any(|known| known == s)) {
+        let what = format!("a status of record type '{}'", record_type);
+        errors.push(unknown("status_name", &what, status, statuses.iter().collect()));
+    }
+    errors
+}
+
+/// An error for a value JobNimbus doesn't know, suggesting a differently
+/// cased match and listing the values it does know.
+fn unknown(field: &str, what: &str, value: &str, known: Vec<&String>) -> FieldError {
+    let mut error = format!("'{}' is not {}", value, what);
+    if let Some(close) = known.iter().find(|k| k.eq_ignore_ascii_case(value)) {
+        error.push_str(&format!("; did you mean '{}'?", close));
+    }
+    let known: Vec<&str> = known.iter().map(|k| k.as_str()).collect();
+    error.push_str(&format!(" Known: {}", if known.is_empty() { "none".to_string() } else { known.join(", ") }));
+    FieldError { field: field.to_string(), error }
+}
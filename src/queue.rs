@@ -0,0 +1,185 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::handlers::Payload;
+use crate::ordering;
+use crate::plugin::PluginRun;
+use crate::routing::Target;
+use crate::sink::SinkResult;
+use crate::store::{self, data_dir};
+
+// Deliveries currently being forwarded by a handler or the worker. Only the
+// holder of a claim may send or complete a delivery.
+static IN_FLIGHT: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+// The queued deliveries by record, so finding the deliveries for a record
+// doesn't mean reading the whole queue. Loaded from disk on first use (and
+// whenever DATA_DIR changes), then kept current by `persist` and `complete`.
+static INDEX: Mutex<Option<(PathBuf, Index)>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Index {
+    /// Record key and attempt count of each queued delivery.
+    by_id: BTreeMap<String, (Option<String>, u32)>,
+    by_record: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Index {
+    fn insert(&mut self, delivery: &Delivery) {
+        self.remove(&delivery.id);
+        let key = ordering::record_key(delivery);
+        if let Some(key) = &key {
+            self.by_record.entry(key.clone()).or_default().insert(delivery.id.clone());
+        }
+        self.by_id.insert(delivery.id.clone(), (key, delivery.attempts));
+    }
+
+    fn remove(&mut self, id: &str) {
+        let Some((Some(key), _)) = self.by_id.remove(id) else { return };
+        if let Some(ids) = self.by_record.get_mut(&key) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.by_record.remove(&key);
+            }
+        }
+    }
+}
+
+/// A payload accepted from Subcontractor Hub that still has to reach JobNimbus.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Delivery {
+    pub id: String,
+    pub received_at: DateTime<Local>,
+    pub payload: Payload,
+    pub body: String,
+    #[serde(default)]
+    pub target: Target,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub next_attempt_at: Option<DateTime<Local>>,
+    /// Later deliveries for the same record that were merged into this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merged: Vec<String>,
+    /// Older than an event already delivered for the same record.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    /// Routing rule that decided where this delivery goes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// How far the delivery got with each sink it is fanned out to.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sinks: BTreeMap<String, SinkResult>,
+    /// The plugins that ran over the inbound event.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginRun>,
+}
+
+impl Delivery {
+    pub fn new(payload: Payload, body: String) -> Self {
+        let received_at = Local::now();
+        // Ids sort in arrival order, which keeps the queue FIFO on disk.
+        let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed) % 10_000;
+        let id = format!("{}-{:04}", received_at.format("%Y%m%d%H%M%S%6f"), seq);
+        Delivery {
+            id,
+            received_at,
+            payload,
+            body,
+            target: Target::default(),
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: None,
+            merged: Vec::new(),
+            stale: false,
+            rule: None,
+            tags: Vec::new(),
+            sinks: BTreeMap::new(),
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Whether the delivery is due for another attempt (no backoff pending).
+    pub fn is_due(&self) -> bool {
+        self.next_attempt_at.is_none_or(|at| at <= Local::now())
+    }
+}
+
+fn queue_dir() -> PathBuf {
+    data_dir().join("queue")
+}
+
+fn with_index<T>(f: impl FnOnce(&mut Index) -> T) -> T {
+    let dir = queue_dir();
+    let mut index = INDEX.lock().unwrap();
+    if index.as_ref().is_none_or(|(loaded_from, _)| *loaded_from != dir) {
+        let mut loaded = Index::default();
+        for delivery in pending() {
+            loaded.insert(&delivery);
+        }
+        *index = Some((dir, loaded));
+    }
+    f(&mut index.as_mut().unwrap().1)
+}
+
+/// Durably writes the delivery to the queue, replacing any previous version.
+pub fn persist(delivery: &Delivery) -> io::Result<()> {
+    store::write_json(&queue_dir(), &delivery.id, delivery)?;
+    with_index(|index| index.insert(delivery));
+    Ok(())
+}
+
+/// Removes a delivery from the queue once it has been dealt with.
+pub fn complete(id: &str) {
+    store::remove_json(&queue_dir(), id);
+    with_index(|index| index.remove(id));
+    release(id);
+}
+
+/// Ids of the queued deliveries for record `key` (see
+/// `ordering::record_key`) with their attempt counts, oldest first.
+pub fn pending_for(key: &str) -> Vec<(String, u32)> {
+    with_index(|index| {
+        index.by_record
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| index.by_id.get(id).map(|(_, attempts)| (id.clone(), *attempts)))
+            .collect()
+    })
+}
+
+/// Marks a delivery as in flight. Returns false if someone else already holds it.
+pub fn claim(id: &str) -> bool {
+    IN_FLIGHT.lock().unwrap().insert(id.to_string())
+}
+
+pub fn release(id: &str) {
+    IN_FLIGHT.lock().unwrap().remove(id);
+}
+
+pub fn is_claimed(id: &str) -> bool {
+    IN_FLIGHT.lock().unwrap().contains(id)
+}
+
+pub fn get(id: &str) -> Option<Delivery> {
+    if !store::is_valid_id(id) {
+        return None;
+    }
+    store::read_json(&queue_dir(), id)
+}
+
+/// All queued deliveries, oldest first.
+pub fn pending() -> Vec<Delivery> {
+    store::list_json(&queue_dir())
+}
@@ -0,0 +1,132 @@
+use actix_web::rt::time::sleep;
+use serde::Serialize;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by every JobNimbus call. Callers reserve a token even
+/// when none is left (the balance goes negative) and sleep until their turn,
+/// so excess calls queue up in order instead of being dropped.
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    waiting: u64,
+}
+
+static BUCKET: Mutex<Option<Bucket>> = Mutex::new(None);
+
+/// Bucket state for the dashboard and `/metrics`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RateLimitStatus {
+    pub enabled: bool,
+    pub rate_per_sec: f64,
+    pub burst: f64,
+    /// Tokens available right now; negative while calls are queued.
+    pub tokens: f64,
+    pub waiting: u64,
+}
+
+/// Calls per second (`JOBNIMBUS_RATE_LIMIT`, default 5, 0 disables).
+fn rate() -> f64 {
+    env::var("JOBNIMBUS_RATE_LIMIT")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|r: &f64| r.is_finite() && *r >= 0.0)
+        .unwrap_or(5.0)
+}
+
+/// Calls allowed back to back after a quiet spell (`JOBNIMBUS_RATE_BURST`, default 10).
+fn burst() -> f64 {
+    env::var("JOBNIMBUS_RATE_BURST")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|b: &f64| b.is_finite() && *b >= 1.0)
+        .unwrap_or(10.0)
+}
+
+/// How long a delivery may wait for a token before it is deferred to the
+/// queue instead (`JOBNIMBUS_RATE_MAX_WAIT_MS`, default 2000).
+pub fn max_wait() -> Duration {
+    let ms = env::var("JOBNIMBUS_RATE_MAX_WAIT_MS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(2_000);
+    Duration::from_millis(ms)
+}
+
+pub fn enabled() -> bool {
+    rate() > 0.0
+}
+
+fn with_bucket<T>(f: impl FnOnce(&mut Bucket, f64, f64) -> T) -> T {
+    let (rate, burst) = (rate(), burst());
+    let mut guard = BUCKET.lock().unwrap();
+    let bucket = guard.get_or_insert_with(|| Bucket { tokens: burst, refilled_at: Instant::now(), waiting: 0 });
+    let now = Instant::now();
+    let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+    bucket.refilled_at = now;
+    f(bucket, rate, burst)
+}
+
+/// How long a call made now would have to wait for its token.
+pub fn delay() -> Duration {
+    if !enabled() {
+        return Duration::ZERO;
+    }
+    with_bucket(|bucket, rate, _| {
+        if bucket.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+        }
+    })
+}
+
+/// Takes a token, waiting for one if the bucket is empty. When that wait
+/// would be longer than `max_wait` no token is taken and the wait is returned.
+pub async fn acquire(max_wait: Duration) -> Result<(), Duration> {
+    if !enabled() {
+        return Ok(());
+    }
+    let wait = with_bucket(|bucket, rate, _| {
+        let wait = if bucket.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+        };
+        if wait > max_wait {
+            return Err(wait);
+        }
+        bucket.tokens -= 1.0;
+        if !wait.is_zero() {
+            bucket.waiting += 1;
+        }
+        Ok(wait)
+    })?;
+    if !wait.is_zero() {
+        let _waiting = Waiting;
+        sleep(wait).await;
+    }
+    Ok(())
+}
+
+// Keeps the waiting count right even if the caller is cancelled mid-sleep.
+struct Waiting;
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        with_bucket(|bucket, _, _| bucket.waiting = bucket.waiting.saturating_sub(1));
+    }
+}
+
+pub fn status() -> RateLimitStatus {
+    let enabled = enabled();
+    with_bucket(|bucket, rate, burst| RateLimitStatus {
+        enabled,
+        rate_per_sec: rate,
+        burst,
+        tokens: (bucket.tokens * 100.0).round() / 100.0,
+        waiting: bucket.waiting,
+    })
+}
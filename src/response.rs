@@ -0,0 +1,99 @@
+use actix_web::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+
+use crate::jobnimbus::UpstreamResponse;
+use crate::log_msg;
+use crate::sink::SinkResult;
+
+pub const DEFAULT_STATUS_MAP: &str = "2xx=200,401=502,403=502,429=503,4xx=422,5xx=502";
+
+/// What we tell the webhook sender about a delivery.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope {
+    /// `delivered`, `rejected`, `queued`, `failed`, `dropped`, `held`, `ignored`
+    /// or `ok` in test mode.
+    pub status: String,
+    /// Absent for events that were never made into a delivery.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery_id: Option<String>,
+    pub upstream_status: Option<u16>,
+    /// The JobNimbus answer, parsed as JSON when it is JSON.
+    pub upstream_body: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Per-sink results when the delivery is fanned out to several sinks.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sinks: BTreeMap<String, SinkResult>,
+}
+
+impl Envelope {
+    pub fn new(status: &str, delivery_id: &str) -> Self {
+        Envelope { delivery_id: Some(delivery_id.to_string()), ..Envelope::without_delivery(status) }
+    }
+
+    /// An answer about an event that never became a delivery.
+    pub fn without_delivery(status: &str) -> Self {
+        Envelope {
+            status: status.to_string(),
+            delivery_id: None,
+            upstream_status: None,
+            upstream_body: None,
+            message: None,
+            sinks: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_upstream(mut self, response: &UpstreamResponse) -> Self {
+        self.upstream_status = Some(response.status);
+        self.upstream_body = Some(parse_body(&response.body));
+        self
+    }
+
+    pub fn with_message(mut self, message: &str) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+
+    pub fn with_sinks(mut self, sinks: &BTreeMap<String, SinkResult>) -> Self {
+        self.sinks = sinks.clone();
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+pub fn parse_body(body: &str) -> Value {
+    serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string()))
+}
+
+/// The status we answer with for an upstream status, from `UPSTREAM_STATUS_MAP`:
+/// comma separated `upstream=inbound` rules where upstream is a code (`401`)
+/// or a class (`4xx`), checked in order. Unmatched statuses pass through.
+pub fn inbound_status(upstream: u16) -> StatusCode {
+    let rules = env::var("UPSTREAM_STATUS_MAP").unwrap_or_else(|_| DEFAULT_STATUS_MAP.to_string());
+    let code = upstream.to_string();
+    for rule in rules.split(',').filter(|r| !r.trim().is_empty()) {
+        let parsed = rule.split_once('=')
+            .and_then(|(pattern, status)| {
+                let status = StatusCode::from_u16(status.trim().parse().ok()?).ok()?;
+                Some((pattern.trim(), status))
+            });
+        let Some((pattern, status)) = parsed else {
+            log_msg(&format!("Ignoring malformed upstream status rule '{}'", rule.trim()), "⚠️");
+            continue;
+        };
+        let matches = match pattern.strip_suffix("xx") {
+            Some(class) => code.starts_with(class) && code.len() == 3,
+            None => pattern == code,
+        };
+        if matches {
+            return status;
+        }
+    }
+    StatusCode::from_u16(upstream).unwrap_or(StatusCode::BAD_GATEWAY)
+}
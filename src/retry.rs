@@ -0,0 +1,85 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::env;
+use std::time::Duration;
+
+/// How often and how patiently we retry a delivery JobNimbus didn't accept.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Fraction of the computed delay to randomly add or subtract (0.0 - 1.0).
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(1000),
+            max_delay: Duration::from_millis(300_000),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        let defaults = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: env_parse("RETRY_MAX_ATTEMPTS").unwrap_or(defaults.max_attempts).max(1),
+            base_delay: env_parse("RETRY_BASE_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.base_delay),
+            max_delay: env_parse("RETRY_MAX_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_delay),
+            jitter: env_parse::<f64>("RETRY_JITTER")
+                .unwrap_or(defaults.jitter)
+                .clamp(0.0, 1.0),
+        }
+    }
+
+    /// Delay before the attempt following `attempt` (1-based), without jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        self.base_delay
+            .saturating_mul(1u32 << exponent)
+            .min(self.max_delay)
+    }
+
+    /// Delay before the next attempt. An upstream `Retry-After` wins over our
+    /// own backoff, up to `max_delay`.
+    pub fn next_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+        let delay = self.backoff(attempt);
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let factor = 1.0 + rand::thread_rng().gen_range(-self.jitter..=self.jitter);
+        delay.mul_f64(factor).min(self.max_delay)
+    }
+}
+
+/// 429 and 5xx are worth another try; any other answer is final.
+pub fn is_retryable_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// Parses a `Retry-After` header value, either delta-seconds or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = at.with_timezone(&Utc).signed_duration_since(Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
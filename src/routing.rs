@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::env;
+use std::fmt;
+
+use crate::handlers::Payload;
+use crate::idmap;
+use crate::log_msg;
+
+/// The JobNimbus endpoint a delivery is written to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    #[default]
+    Contacts,
+    Jobs,
+    Tasks,
+    Activities,
+}
+
+impl Target {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "contacts" | "contact" => Some(Target::Contacts),
+            "jobs" | "job" => Some(Target::Jobs),
+            "tasks" | "task" => Some(Target::Tasks),
+            "activities" | "activity" => Some(Target::Activities),
+            _ => None,
+        }
+    }
+
+    /// Path of the endpoint relative to the JobNimbus API base.
+    pub fn path(&self) -> &'static str {
+        match self {
+            Target::Contacts => "/contacts",
+            Target::Jobs => "/jobs",
+            Target::Tasks => "/tasks",
+            Target::Activities => "/activities",
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path()[1..])
+    }
+}
+
+/// The `event` field of an inbound payload, if any.
+pub fn event_name(payload: &Payload) -> Option<&str> {
+    payload._extra.get("event").and_then(|v| v.as_str())
+}
+
+/// Matches `text` against a glob where `*` is any run of characters and `?` any one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn globs(key: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty())
+        .collect()
+}
+
+/// Why an event should be acknowledged without forwarding, if it should.
+/// `EVENT_DENYLIST` wins over `EVENT_ALLOWLIST`; with an allowlist set,
+/// anything it doesn't match (including payloads without an event) is ignored.
+pub fn ignore_reason(event: Option<&str>) -> Option<String> {
+    let name = event.unwrap_or("");
+    if let Some(pattern) = globs("EVENT_DENYLIST").into_iter().find(|g| glob_match(g, name)) {
+        return Some(format!("Event '{}' is on the deny list ({})", name, pattern));
+    }
+    let allowed = globs("EVENT_ALLOWLIST");
+    if !allowed.is_empty() && !allowed.iter().any(|g| glob_match(g, name)) {
+        return Some(format!("Event '{}' is not on the allow list", name));
+    }
+    None
+}
+
+/// Picks the endpoint for an event from `EVENT_ROUTES`, a comma separated list of
+/// `pattern=target` pairs checked in order. Unmatched events go to contacts.
+pub fn route(event: Option<&str>) -> Target {
+    let routes = env::var("EVENT_ROUTES").unwrap_or_default();
+    let event = event.unwrap_or("");
+    for rule in routes.split(',').filter(|r| !r.trim().is_empty()) {
+        let Some((pattern, target)) = rule.split_once('=') else {
+            log_msg(&format!("Ignoring malformed event route '{}'", rule.trim()), "⚠️");
+            continue;
+        };
+        if !glob_match(pattern.trim(), event) {
+            continue;
+        }
+        match Target::parse(target) {
+            Some(target) => return target,
+            None => log_msg(&format!("Ignoring event route with unknown target '{}'", target.trim()), "⚠️"),
+        }
+    }
+    Target::Contacts
+}
+
+/// Adapts an outbound object to what the target endpoint expects, linking
+/// jobs, tasks and activities to the contact of the SCH customer they belong to.
+pub fn shape(target: Target, body: Value, payload: &Payload) -> Value {
+    let mut object = match body {
+        Value::Object(object) => object,
+        other => return other,
+    };
+    if target == Target::Contacts {
+        return Value::Object(object);
+    }
+
+    let contact = contact_jnid(payload).map(|jnid| serde_json::json!({ "id": jnid }));
+    match target {
+        Target::Jobs => {
+            if let Some(contact) = &contact {
+                object.entry("primary").or_insert_with(|| contact.clone());
+                object.entry("related").or_insert_with(|| Value::Array(vec![contact.clone()]));
+            }
+        }
+        Target::Tasks => {
+            fill_from(&mut object, "title", &["name", "subject"]);
+            if let Some(contact) = &contact {
+                object.entry("related").or_insert_with(|| Value::Array(vec![contact.clone()]));
+            }
+        }
+        Target::Activities => {
+            fill_from(&mut object, "note", &["description", "message"]);
+            object.entry("record_type_name").or_insert_with(|| Value::from("Note"));
+            if let Some(contact) = &contact {
+                object.entry("primary").or_insert_with(|| contact.clone());
+            }
+        }
+        Target::Contacts => {}
+    }
+    Value::Object(object)
+}
+
+/// The jnid of the contact for the SCH customer referenced at
+/// `SCH_CONTACT_REF_FIELD` (default `customer_id`).
+fn contact_jnid(payload: &Payload) -> Option<String> {
+    let field = env::var("SCH_CONTACT_REF_FIELD").unwrap_or_else(|_| "customer_id".to_string());
+    idmap::id_at(payload, &field).and_then(|sch_id| idmap::lookup_jnid(&sch_id))
+}
+
+fn fill_from(object: &mut Map<String, Value>, field: &str, fallbacks: &[&str]) {
+    if object.contains_key(field) {
+        return;
+    }
+    if let Some(value) = fallbacks.iter().find_map(|f| object.get(*f).cloned()) {
+        object.insert(field.to_string(), value);
+    }
+}
@@ -0,0 +1,284 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::config_file::Reloadable;
+use crate::field_map::{self, resolve_in};
+use crate::handlers::Payload;
+use crate::log_msg;
+use crate::routing::{event_name, glob_match, Target};
+
+/// The contents of the rules file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+/// A condition and what to do with payloads that meet it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub when: Condition,
+    pub action: Action,
+    /// Endpoint for `route` (and optionally `tag`).
+    #[serde(default)]
+    pub target: Option<Target>,
+    /// Named field mapping to build the outbound object with.
+    #[serde(default)]
+    pub mapping: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Acknowledge the payload without sending it anywhere.
+    Drop,
+    /// Send it, to `target` and/or with `mapping` if given.
+    Route,
+    /// Send it as usual with `tags` recorded on the delivery.
+    Tag,
+    /// Park it in the dead-letter store until someone replays or discards it.
+    Hold,
+}
+
+/// Every clause that is present must hold. An empty condition matches everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Condition {
+    /// Event name glob.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    /// Path the comparisons below apply to (relative to `data`, `$.` for the whole payload).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_equals: Option<Value>,
+    /// Regular expression the field's text must match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Pattern>,
+    /// Whether the field must be present (non-null) or absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub present: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gte: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lte: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub all: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub any: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not: Option<Box<Condition>>,
+}
+
+/// A regular expression, compiled when the rules are loaded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, String> {
+        Regex::new(&pattern).map(Pattern).map_err(|e| format!("invalid regex '{}': {}", pattern, e))
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> String {
+        pattern.0.as_str().to_string()
+    }
+}
+
+/// The outcome of evaluating the rules for one payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Name (or `#n` position) of the matching rule; `None` when no rule matched.
+    pub rule: Option<String>,
+    pub action: Action,
+    pub target: Option<Target>,
+    pub mapping: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl Decision {
+    fn default_route() -> Self {
+        Decision { rule: None, action: Action::Route, target: None, mapping: None, tags: Vec::new() }
+    }
+
+    /// One-line description for the delivery log.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("{:?}", self.action).to_lowercase()];
+        if let Some(target) = self.target {
+            parts.push(format!("to {}", target));
+        }
+        if let Some(mapping) = &self.mapping {
+            parts.push(format!("with mapping '{}'", mapping));
+        }
+        if !self.tags.is_empty() {
+            parts.push(format!("tagged {}", self.tags.join(", ")));
+        }
+        format!("rule '{}' matched: {}", self.rule.as_deref().unwrap_or("?"), parts.join(" "))
+    }
+}
+
+/// The rules file (`RULES_FILE`, default `config/rules.json`). No rules
+/// file means every payload is forwarded.
+static RULES: Reloadable<RulesConfig> =
+    Reloadable::new("routing rules", "RULES_FILE", "config/rules.json").validated_by(validate);
+
+/// The current rules, re-read whenever the file changes on disk.
+pub fn current() -> Arc<RulesConfig> {
+    RULES.current()
+}
+
+/// Forces a reload, reporting errors to the caller.
+pub fn reload() -> Result<Arc<RulesConfig>, String> {
+    let config = RULES.reload()?;
+    log_msg(&format!("Routing rules reloaded ({} rules)", config.rules.len()), "🔄");
+    Ok(config)
+}
+
+fn rule_name(rule: &Rule, index: usize) -> String {
+    rule.name.clone().unwrap_or_else(|| format!("#{}", index + 1))
+}
+
+/// Rejects `route` rules with nothing to route by and mappings that don't
+/// exist up front rather than on the first payload they match. Mappings are
+/// looked up in the current mappings file.
+pub fn validate(config: &RulesConfig) -> Result<(), String> {
+    for (index, rule) in config.rules.iter().enumerate() {
+        if rule.action == Action::Route && rule.target.is_none() && rule.mapping.is_none() {
+            return Err(format!("rule '{}': route needs a target or a mapping", rule_name(rule, index)));
+        }
+        if let Some(mapping) = &rule.mapping {
+            if !field_map::has_named(mapping) {
+                return Err(format!("rule '{}': no mapping named '{}'", rule_name(rule, index), mapping));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates the rules in order; the first one that matches decides.
+/// Payloads no rule matches are routed as usual.
+pub fn evaluate(payload: &Payload) -> Decision {
+    let config = current();
+    config.rules.iter()
+        .enumerate()
+        .find(|(_, rule)| matches(&rule.when, payload))
+        .map(|(index, rule)| Decision {
+            rule: Some(rule_name(rule, index)),
+            action: rule.action,
+            target: rule.target,
+            mapping: rule.mapping.clone(),
+            tags: rule.tags.clone(),
+        })
+        .unwrap_or_else(Decision::default_route)
+}
+
+pub fn matches(condition: &Condition, payload: &Payload) -> bool {
+    if let Some(pattern) = &condition.event {
+        if !glob_match(pattern, event_name(payload).unwrap_or("")) {
+            return false;
+        }
+    }
+
+    let value = condition.field.as_deref()
+        .and_then(|path| resolve_in(payload, path))
+        .filter(|v| !v.is_null());
+    let checks_field = condition.equals.is_some()
+        || condition.not_equals.is_some()
+        || condition.matches.is_some()
+        || condition.present.is_some()
+        || condition.gt.is_some()
+        || condition.gte.is_some()
+        || condition.lt.is_some()
+        || condition.lte.is_some();
+    if checks_field && !field_matches(condition, value.as_ref()) {
+        return false;
+    }
+
+    condition.all.iter().all(|c| matches(c, payload))
+        && (condition.any.is_empty() || condition.any.iter().any(|c| matches(c, payload)))
+        && !condition.not.as_deref().is_some_and(|c| matches(c, payload))
+}
+
+fn field_matches(condition: &Condition, value: Option<&Value>) -> bool {
+    if let Some(present) = condition.present {
+        if value.is_some() != present {
+            return false;
+        }
+    }
+    if let Some(expected) = &condition.equals {
+        if !value.is_some_and(|v| loosely_equal(v, expected)) {
+            return false;
+        }
+    }
+    if let Some(unexpected) = &condition.not_equals {
+        if value.is_some_and(|v| loosely_equal(v, unexpected)) {
+            return false;
+        }
+    }
+    if let Some(Pattern(regex)) = &condition.matches {
+        if !value.and_then(as_text).is_some_and(|text| regex.is_match(&text)) {
+            return false;
+        }
+    }
+
+    let comparisons = [
+        (condition.gt, f64::gt as fn(&f64, &f64) -> bool),
+        (condition.gte, f64::ge),
+        (condition.lt, f64::lt),
+        (condition.lte, f64::le),
+    ];
+    for (bound, compare) in comparisons {
+        if let Some(bound) = bound {
+            match value.and_then(as_number) {
+                Some(n) if compare(&n, &bound) => {}
+                _ => return false,
+            }
+        }
+    }
+    true
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Numbers compare by value and "5" equals 5, since SCH isn't consistent
+/// about quoting numbers.
+fn loosely_equal(value: &Value, expected: &Value) -> bool {
+    if value == expected {
+        return true;
+    }
+    match (as_number(value), as_number(expected)) {
+        (Some(a), Some(b)) => a == b,
+        _ => as_text(value).is_some_and(|a| as_text(expected).is_some_and(|b| a == b)),
+    }
+}
@@ -0,0 +1,199 @@
+use jsonschema::Validator;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, metadata, read_to_string};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::handlers::Payload;
+use crate::log_msg;
+use crate::routing::{event_name, glob_match};
+
+/// What happens to a payload that doesn't match its schema.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaMode {
+    /// Reject it with `422`.
+    Enforce,
+    /// Log the violations and carry on.
+    Warn,
+}
+
+impl SchemaMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "enforce" => Some(SchemaMode::Enforce),
+            "warn" => Some(SchemaMode::Warn),
+            _ => None,
+        }
+    }
+}
+
+/// One way a payload breaks its schema.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// JSON pointer to the offending value inside `data` (`""` for `data` itself).
+    pub pointer: String,
+    pub message: String,
+    /// JSON pointer to the schema keyword that failed.
+    pub schema_path: String,
+}
+
+/// The result of validating a payload that has a schema.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// File name of the schema.
+    pub schema: String,
+    pub mode: SchemaMode,
+    pub violations: Vec<Violation>,
+}
+
+/// The schema for a payload's event that failed to load, so the payload
+/// couldn't be checked.
+#[derive(Debug, Clone)]
+pub struct Broken {
+    /// File name of the schema.
+    pub schema: String,
+    /// `SCHEMA_MODE`, since the schema's own mode can't be read.
+    pub mode: SchemaMode,
+    pub error: String,
+}
+
+/// A schema file, as reported by `GET /schemas`.
+#[derive(Serialize, Debug, Clone)]
+pub struct SchemaInfo {
+    pub name: String,
+    /// The event name or glob the schema applies to.
+    pub event: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<SchemaMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Compiled {
+    /// Mode set by the schema's `x-schema-mode`, overriding `SCHEMA_MODE`.
+    mode: Option<SchemaMode>,
+    validator: Arc<Validator>,
+}
+
+struct Loaded {
+    modified: Option<SystemTime>,
+    schema: Result<Compiled, String>,
+}
+
+// Compiled schemas by file name, recompiled when the file changes.
+static SCHEMAS: Mutex<BTreeMap<String, Loaded>> = Mutex::new(BTreeMap::new());
+
+/// Directory schemas are loaded from (`SCHEMAS_DIR`, default `config/schemas`).
+pub fn schemas_dir() -> PathBuf {
+    PathBuf::from(env::var("SCHEMAS_DIR").unwrap_or_else(|_| "config/schemas".to_string()))
+}
+
+/// Mode for schemas that don't set their own (`SCHEMA_MODE`, default `enforce`).
+fn default_mode() -> SchemaMode {
+    env::var("SCHEMA_MODE").ok().and_then(|v| SchemaMode::parse(&v)).unwrap_or(SchemaMode::Enforce)
+}
+
+/// Picks up schema files that appeared or changed and forgets removed ones.
+fn refresh(schemas: &mut BTreeMap<String, Loaded>) {
+    let mut found = BTreeMap::new();
+    if let Ok(entries) = fs::read_dir(schemas_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                    found.insert(name.to_string(), path);
+                }
+            }
+        }
+    }
+
+    schemas.retain(|name, _| found.contains_key(name));
+    for (name, path) in found {
+        let modified = metadata(&path).and_then(|m| m.modified()).ok();
+        if schemas.get(&name).is_some_and(|loaded| loaded.modified == modified) {
+            continue;
+        }
+        let schema = compile(&path);
+        if let Err(e) = &schema {
+            log_msg(&format!("Failed to load schema {}: {}", name, e), "❌");
+        }
+        schemas.insert(name, Loaded { modified, schema });
+    }
+}
+
+fn compile(path: &PathBuf) -> Result<Compiled, String> {
+    let content = read_to_string(path).map_err(|e| e.to_string())?;
+    let schema: Value = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    let mode = match schema.get("x-schema-mode") {
+        None => None,
+        Some(value) => Some(
+            value.as_str()
+                .and_then(SchemaMode::parse)
+                .ok_or("x-schema-mode must be \"enforce\" or \"warn\"")?,
+        ),
+    };
+    let validator = jsonschema::validator_for(&schema).map_err(|e| e.to_string())?;
+    Ok(Compiled { mode, validator: Arc::new(validator) })
+}
+
+/// The event name (or glob) a schema file applies to: its name without `.json`.
+fn event_pattern(name: &str) -> &str {
+    name.strip_suffix(".json").unwrap_or(name)
+}
+
+/// The schemas currently in the schemas directory.
+pub fn loaded() -> Vec<SchemaInfo> {
+    let mut schemas = SCHEMAS.lock().unwrap();
+    refresh(&mut schemas);
+    schemas
+        .iter()
+        .map(|(name, loaded)| SchemaInfo {
+            name: name.clone(),
+            event: event_pattern(name).to_string(),
+            mode: loaded.schema.as_ref().ok().map(|c| c.mode.unwrap_or_else(default_mode)),
+            error: loaded.schema.as_ref().err().cloned(),
+        })
+        .collect()
+}
+
+/// Validates a payload's `data` against the schema for its event: the file
+/// named after the event, or else the first (by name) whose glob matches.
+/// `Ok(None)` when no schema applies, and `Err` when the one that applies
+/// failed to load; a broken exact match doesn't fall back to the globs.
+pub fn validate(payload: &Payload) -> Result<Option<Report>, Broken> {
+    let Some(event) = event_name(payload) else {
+        return Ok(None);
+    };
+    let (name, mode, validator) = {
+        let mut schemas = SCHEMAS.lock().unwrap();
+        refresh(&mut schemas);
+        let exact = format!("{}.json", event);
+        let Some((name, loaded)) = schemas.get_key_value(&exact).or_else(|| {
+            schemas.iter().find(|(name, _)| glob_match(event_pattern(name), event))
+        }) else {
+            return Ok(None);
+        };
+        let compiled = loaded.schema.as_ref().map_err(|error| Broken {
+            schema: name.clone(),
+            mode: default_mode(),
+            error: error.clone(),
+        })?;
+        (name.clone(), compiled.mode.unwrap_or_else(default_mode), compiled.validator.clone())
+    };
+
+    let data = payload.data.as_ref().unwrap_or(&Value::Null);
+    let violations = validator
+        .iter_errors(data)
+        .map(|error| Violation {
+            pointer: error.instance_path.to_string(),
+            message: error.to_string(),
+            schema_path: error.schema_path.to_string(),
+        })
+        .collect();
+    Ok(Some(Report { schema: name, mode, violations }))
+}
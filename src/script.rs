@@ -0,0 +1,130 @@
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{metadata, read_to_string};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::handlers::Payload;
+use crate::log_msg;
+use crate::routing::event_name;
+
+/// What a script decided to do with a payload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptOutcome {
+    /// Send this object.
+    Send(Value),
+    /// Don't send anything, for this reason.
+    Drop(String),
+}
+
+/// Returned by `drop()` in a script.
+#[derive(Debug, Clone)]
+struct DropDecision(String);
+
+/// A compiled script and the modification time of the file it came from.
+type Compiled = (Option<SystemTime>, Arc<AST>);
+
+// Compiled scripts, recompiled when the file changes.
+static COMPILED: Mutex<Option<HashMap<PathBuf, Compiled>>> = Mutex::new(None);
+
+/// Directory scripts are loaded from (`SCRIPTS_DIR`, default `config/scripts`).
+pub fn scripts_dir() -> PathBuf {
+    PathBuf::from(env::var("SCRIPTS_DIR").unwrap_or_else(|_| "config/scripts".to_string()))
+}
+
+/// Operations a script may run before it is stopped (`SCRIPT_MAX_OPERATIONS`, default 100000).
+fn max_operations() -> u64 {
+    env::var("SCRIPT_MAX_OPERATIONS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(100_000)
+}
+
+/// Wall-clock limit for one run (`SCRIPT_TIMEOUT_MS`, default 1000).
+fn timeout() -> Duration {
+    let ms = env::var("SCRIPT_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(1_000);
+    Duration::from_millis(ms)
+}
+
+/// A sandboxed engine: no `eval`, no file or network access, and bounded
+/// operations, recursion, run time and value sizes.
+fn engine(deadline: Instant) -> Engine {
+    let mut engine = Engine::new();
+    // The default resolver loads any `.rhai` file named by an `import`.
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(max_operations());
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1 << 20);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.disable_symbol("eval");
+    engine.on_progress(move |_| (Instant::now() > deadline).then(|| Dynamic::from("timed out")));
+    engine.on_print(|text| log_msg(&format!("Script: {}", text), "📜"));
+    engine.on_debug(|text, _, pos| log_msg(&format!("Script debug ({}): {}", pos, text), "📜"));
+    engine.register_type_with_name::<DropDecision>("Drop");
+    engine.register_fn("drop", |reason: &str| DropDecision(reason.to_string()));
+    engine.register_fn("drop", || DropDecision("Dropped by script".to_string()));
+    engine
+}
+
+fn script_path(name: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(name);
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err("script names must be relative paths inside SCRIPTS_DIR".to_string());
+    }
+    Ok(scripts_dir().join(relative))
+}
+
+fn compiled(engine: &Engine, name: &str) -> Result<Arc<AST>, String> {
+    let path = script_path(name)?;
+    let modified = metadata(&path).and_then(|m| m.modified()).ok();
+    let mut cache = COMPILED.lock().unwrap();
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if let Some((at, ast)) = cache.get(&path) {
+        if *at == modified {
+            return Ok(ast.clone());
+        }
+    }
+
+    let source = read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let ast = Arc::new(engine.compile(&source).map_err(|e| e.to_string())?);
+    cache.insert(path, (modified, ast.clone()));
+    Ok(ast)
+}
+
+/// Runs script `name` for a payload. The script sees the inbound `data`, the
+/// `event` name, the whole `payload` and `out`, the object field mapping
+/// built. It returns the object to send, `drop(reason)`, or nothing to send
+/// `out` as it left it. Errors carry the script's line and position.
+pub fn run(name: &str, payload: &Payload, out: Value) -> Result<ScriptOutcome, String> {
+    let engine = engine(Instant::now() + timeout());
+    let fail = |e: String| format!("Script {}: {}", name, e);
+    let ast = compiled(&engine, name).map_err(fail)?;
+
+    let mut scope = Scope::new();
+    let data = payload.data.clone().unwrap_or(Value::Null);
+    let whole = serde_json::to_value(payload).unwrap_or(Value::Null);
+    scope.push("data", to_dynamic(data).map_err(|e| fail(e.to_string()))?);
+    scope.push("event", event_name(payload).unwrap_or("").to_string());
+    scope.push("payload", to_dynamic(whole).map_err(|e| fail(e.to_string()))?);
+    scope.push("out", to_dynamic(out).map_err(|e| fail(e.to_string()))?);
+
+    let result: Dynamic = engine.eval_ast_with_scope(&mut scope, &ast).map_err(|e| fail(e.to_string()))?;
+    if let Some(decision) = result.clone().try_cast::<DropDecision>() {
+        return Ok(ScriptOutcome::Drop(decision.0));
+    }
+    let result = if result.is_unit() { scope.get_value::<Dynamic>("out").unwrap_or_default() } else { result };
+    if !result.is_map() {
+        return Err(fail(format!("expected an object map or drop(), got {}", result.type_name())));
+    }
+    from_dynamic::<Value>(&result).map(ScriptOutcome::Send).map_err(|e| fail(e.to_string()))
+}
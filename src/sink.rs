@@ -0,0 +1,347 @@
+use regex::{Captures, Regex};
+use reqwest::{Client, Method, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::{create_dir_all, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::config_file::Reloadable;
+use crate::jobnimbus::{self, UpstreamResponse};
+use crate::plugin;
+use crate::queue::Delivery;
+use crate::routing;
+
+pub type SendFuture<'a> = Pin<Box<dyn Future<Output = Result<UpstreamResponse, String>> + Send + 'a>>;
+
+/// A destination deliveries are written to. Like a JobNimbus call, `send`
+/// returns `Err` only when there is no complete answer and the delivery is
+/// safe to try again; any answer is classified by its status code.
+pub trait Sink: Send + Sync {
+    /// Unique name the sink's results are tracked under.
+    fn name(&self) -> &str;
+
+    /// How the sink is referred to in logs and errors.
+    fn label(&self) -> String {
+        format!("Sink '{}'", self.name())
+    }
+
+    /// Whether the sink calls JobNimbus, and so waits on its rate limiter and
+    /// circuit breaker.
+    fn uses_jobnimbus(&self) -> bool {
+        false
+    }
+
+    fn send<'a>(&'a self, client: &'a Client, delivery: &'a Delivery) -> SendFuture<'a>;
+}
+
+/// Where a delivery stands with one sink.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkState {
+    #[default]
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SinkResult {
+    pub status: SinkState,
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SinkResult {
+    pub fn is_done(&self) -> bool {
+        self.status != SinkState::Pending
+    }
+
+    pub fn update(&mut self, status: SinkState, response: Option<&UpstreamResponse>, error: Option<&str>) {
+        self.status = status;
+        self.upstream_status = response.map(|r| r.status);
+        self.error = error.map(str::to_string);
+    }
+}
+
+/// One entry of the sinks file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    Jobnimbus {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    Webhook {
+        #[serde(default)]
+        name: Option<String>,
+        url: String,
+        #[serde(default = "default_method")]
+        method: String,
+        /// `${VAR}` in a value is replaced with that environment variable.
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    File {
+        #[serde(default)]
+        name: Option<String>,
+        path: PathBuf,
+    },
+    Plugin {
+        #[serde(default)]
+        name: Option<String>,
+        /// File name of a WebAssembly plugin in `PLUGINS_DIR`.
+        plugin: String,
+    },
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+impl SinkConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            SinkConfig::Jobnimbus { name } => name.as_deref().unwrap_or("jobnimbus"),
+            SinkConfig::Webhook { name, .. } => name.as_deref().unwrap_or("webhook"),
+            SinkConfig::File { name, .. } => name.as_deref().unwrap_or("file"),
+            SinkConfig::Plugin { name, .. } => name.as_deref().unwrap_or("plugin"),
+        }
+    }
+
+    pub fn build(&self) -> Result<Box<dyn Sink>, String> {
+        let name = self.name().to_string();
+        Ok(match self {
+            SinkConfig::Jobnimbus { .. } => Box::new(JobNimbusSink { name }),
+            SinkConfig::Webhook { url, method, headers, .. } => {
+                Url::parse(url).map_err(|e| format!("invalid url '{}': {}", url, e))?;
+                let method = Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes())
+                    .map_err(|_| format!("invalid method '{}'", method))?;
+                Box::new(WebhookSink {
+                    name,
+                    url: url.clone(),
+                    method,
+                    headers: headers.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                })
+            }
+            SinkConfig::File { path, .. } => {
+                if path.as_os_str().is_empty() {
+                    return Err("file sink needs a path".to_string());
+                }
+                Box::new(FileSink { name, path: path.clone() })
+            }
+            SinkConfig::Plugin { plugin, .. } => {
+                if plugin.trim().is_empty() {
+                    return Err("plugin sink needs a plugin".to_string());
+                }
+                Box::new(PluginSink { name, plugin: plugin.clone() })
+            }
+        })
+    }
+}
+
+/// The contents of the sinks file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SinksConfig {
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+}
+
+/// The sinks file (`SINKS_FILE`, default `config/sinks.json`). No sinks
+/// file means JobNimbus only.
+static SINKS: Reloadable<SinksConfig> =
+    Reloadable::new("sinks", "SINKS_FILE", "config/sinks.json").validated_by(validate);
+
+/// The current sinks config, re-read whenever the file changes on disk.
+pub fn current() -> Arc<SinksConfig> {
+    SINKS.current()
+}
+
+fn validate(config: &SinksConfig) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for sink in &config.sinks {
+        if !names.insert(sink.name()) {
+            return Err(format!("more than one sink named '{}'", sink.name()));
+        }
+        sink.build().map_err(|e| format!("sink '{}': {}", sink.name(), e))?;
+    }
+    Ok(())
+}
+
+/// The sinks every delivery is fanned out to, in configured order. Without a
+/// sinks file (or with an empty one) that is just JobNimbus.
+pub fn configured() -> Vec<Box<dyn Sink>> {
+    let config = current();
+    let sinks: Vec<Box<dyn Sink>> = config.sinks.iter().filter_map(|sink| sink.build().ok()).collect();
+    if sinks.is_empty() {
+        return vec![Box::new(JobNimbusSink { name: "jobnimbus".to_string() })];
+    }
+    sinks
+}
+
+/// Writes to the JobNimbus endpoint the delivery was routed to.
+pub struct JobNimbusSink {
+    name: String,
+}
+
+impl Sink for JobNimbusSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label(&self) -> String {
+        "Job Nimbus".to_string()
+    }
+
+    fn uses_jobnimbus(&self) -> bool {
+        true
+    }
+
+    fn send<'a>(&'a self, client: &'a Client, delivery: &'a Delivery) -> SendFuture<'a> {
+        Box::pin(jobnimbus::forward(client, delivery))
+    }
+}
+
+/// Sends the outbound body to an arbitrary HTTP endpoint.
+pub struct WebhookSink {
+    name: String,
+    url: String,
+    method: Method,
+    headers: Vec<(String, String)>,
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label(&self) -> String {
+        format!("Webhook '{}'", self.name)
+    }
+
+    fn send<'a>(&'a self, client: &'a Client, delivery: &'a Delivery) -> SendFuture<'a> {
+        Box::pin(async move {
+            let mut request = client.request(self.method.clone(), &self.url)
+                .header("Content-Type", "application/json")
+                .header("X-Delivery-Id", &delivery.id);
+            for (name, value) in &self.headers {
+                request = request.header(name, expand_env(value));
+            }
+            let response = request.body(delivery.body.clone())
+                .send()
+                .await
+                .map_err(|e| format!("HTTP request error: {}", e))?;
+            UpstreamResponse::read(response).await
+        })
+    }
+}
+
+/// Appends each delivery as one line of JSON to a local file.
+pub struct FileSink {
+    name: String,
+    path: PathBuf,
+}
+
+// Keeps concurrent deliveries from interleaving their lines.
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+impl Sink for FileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label(&self) -> String {
+        format!("File sink '{}'", self.name)
+    }
+
+    fn send<'a>(&'a self, _client: &'a Client, delivery: &'a Delivery) -> SendFuture<'a> {
+        Box::pin(async move {
+            let line = summary(delivery).to_string();
+            // File writes block, so they happen off the async workers.
+            let path = self.path.clone();
+            actix_web::rt::task::spawn_blocking(move || append_line(&path, &line))
+                .await
+                .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+                .and_then(|result| result)?;
+            Ok(UpstreamResponse { status: 200, body: String::new(), retry_after: None, limited: false })
+        })
+    }
+}
+
+fn append_line(path: &Path, line: &str) -> Result<(), String> {
+    let _lock = FILE_LOCK.lock().unwrap();
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Hands each delivery to the `deliver` export of a WebAssembly plugin, which
+/// answers with a status code classified like an HTTP one.
+pub struct PluginSink {
+    name: String,
+    plugin: String,
+}
+
+impl Sink for PluginSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label(&self) -> String {
+        format!("Plugin sink '{}'", self.name)
+    }
+
+    fn send<'a>(&'a self, _client: &'a Client, delivery: &'a Delivery) -> SendFuture<'a> {
+        Box::pin(async move {
+            let message = summary(delivery).to_string();
+            // The plugin runs off the async workers, like any CPU bound call.
+            let plugin_name = self.plugin.clone();
+            let status = actix_web::rt::task::spawn_blocking(move || plugin::deliver(&plugin_name, message.as_bytes()))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|result| result)
+                .map_err(|e| format!("Plugin {}: {}", self.plugin, e))?;
+            let status = u16::try_from(status)
+                .ok()
+                .filter(|status| (100..600).contains(status))
+                .ok_or_else(|| format!("Plugin {}: deliver returned {}, not a status code", self.plugin, status))?;
+            Ok(UpstreamResponse { status, body: String::new(), retry_after: None, limited: false })
+        })
+    }
+}
+
+/// What file and plugin sinks are given: the delivery's id, received time,
+/// event, target and outbound body.
+fn summary(delivery: &Delivery) -> serde_json::Value {
+    let body: serde_json::Value = serde_json::from_str(&delivery.body)
+        .unwrap_or_else(|_| serde_json::Value::String(delivery.body.clone()));
+    serde_json::json!({
+        "delivery_id": delivery.id,
+        "received_at": delivery.received_at,
+        "event": routing::event_name(&delivery.payload),
+        "target": delivery.target,
+        "body": body,
+    })
+}
+
+/// Replaces `${NAME}` with the environment variable `NAME` (empty if unset).
+fn expand_env(value: &str) -> String {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN
+        .get_or_init(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap())
+        .replace_all(value, |caps: &Captures| env::var(&caps[1]).unwrap_or_default())
+        .into_owned()
+}
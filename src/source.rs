@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::Arc;
+
+use crate::config_file::Reloadable;
+use crate::field_map::resolve;
+use crate::handlers::Payload;
+
+/// How a source's request bodies are laid out.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceFormat {
+    /// The Subcontractor Hub envelope: `{ "event": ..., "data": { ... } }`.
+    Sch,
+    /// Any JSON object; `data_path` picks the record and `event_path` the event name.
+    Json,
+    /// `application/x-www-form-urlencoded` web-form posts.
+    Form,
+    /// A JSON array of events, each read like `json` (or as SCH envelopes when
+    /// no paths are configured).
+    Batch,
+}
+
+/// A named inbound endpoint, `POST /in/{name}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SourceConfig {
+    pub format: SourceFormat,
+    /// Path of the record inside each event; the whole event when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_path: Option<String>,
+    /// Path of the event name inside each event (a field name for forms).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_path: Option<String>,
+    /// Event name for events that don't carry one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+}
+
+/// The contents of the sources file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SourcesConfig {
+    #[serde(default)]
+    pub sources: BTreeMap<String, SourceConfig>,
+}
+
+/// The sources file (`SOURCES_FILE`, default `config/sources.json`). No
+/// sources file leaves just the built-in `sch` source.
+static SOURCES: Reloadable<SourcesConfig> = Reloadable::new("sources", "SOURCES_FILE", "config/sources.json");
+
+/// The current sources config, re-read whenever the file changes on disk.
+pub fn current() -> Arc<SourcesConfig> {
+    SOURCES.current()
+}
+
+/// Most events accepted in one batch request (`SOURCE_MAX_BATCH`, default 100).
+pub fn max_batch() -> usize {
+    env::var("SOURCE_MAX_BATCH")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(100)
+}
+
+/// The source called `name`. `sch` is always available, even when the
+/// sources file doesn't mention it.
+pub fn find(name: &str) -> Option<SourceConfig> {
+    current().sources.get(name).cloned().or_else(|| {
+        (name == "sch").then_some(SourceConfig {
+            format: SourceFormat::Sch,
+            data_path: None,
+            event_path: None,
+            event: None,
+        })
+    })
+}
+
+/// Turns a request body from source `name` into the events it carries, each
+/// tagged with the source's name in `source` unless it already has one.
+pub fn normalize(name: &str, source: &SourceConfig, body: &[u8]) -> Result<Vec<Payload>, String> {
+    let mut payloads = match source.format {
+        SourceFormat::Sch => vec![sch_event(parse_json(body)?)?],
+        SourceFormat::Json => match parse_json(body)? {
+            Value::Array(_) => return Err("Expected a JSON object; use a batch source for arrays".to_string()),
+            value => vec![json_event(source, value)],
+        },
+        SourceFormat::Form => vec![form_event(source, body)?],
+        SourceFormat::Batch => {
+            let Value::Array(items) = parse_json(body)? else {
+                return Err("Expected a JSON array of events".to_string());
+            };
+            let has_paths = source.data_path.is_some() || source.event_path.is_some();
+            items.into_iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    if has_paths {
+                        Ok(json_event(source, item))
+                    } else {
+                        sch_event(item).map_err(|e| format!("Event {}: {}", index, e))
+                    }
+                })
+                .collect::<Result<Vec<_>, String>>()?
+        }
+    };
+
+    for payload in &mut payloads {
+        if let Some(event) = &source.event {
+            payload._extra.entry("event".to_string()).or_insert_with(|| Value::String(event.clone()));
+        }
+        payload._extra.entry("source".to_string()).or_insert_with(|| Value::String(name.to_string()));
+    }
+    Ok(payloads)
+}
+
+fn parse_json(body: &[u8]) -> Result<Value, String> {
+    serde_json::from_slice(body).map_err(|e| format!("Invalid JSON: {}", e))
+}
+
+fn sch_event(value: Value) -> Result<Payload, String> {
+    if !value.is_object() {
+        return Err("Expected a JSON object".to_string());
+    }
+    serde_json::from_value(value).map_err(|e| format!("Invalid event: {}", e))
+}
+
+fn json_event(source: &SourceConfig, value: Value) -> Payload {
+    let event = source.event_path.as_deref()
+        .and_then(|path| resolve(&value, path))
+        .filter(|event| event.is_string());
+    let data = match source.data_path.as_deref() {
+        Some(path) => resolve(&value, path),
+        None => Some(value),
+    };
+    let mut extra = HashMap::new();
+    if let Some(event) = event {
+        extra.insert("event".to_string(), event);
+    }
+    Payload { data, _extra: extra }
+}
+
+/// Form fields become `data`; a field given more than once becomes an array.
+fn form_event(source: &SourceConfig, body: &[u8]) -> Result<Payload, String> {
+    let fields: Vec<(String, String)> =
+        serde_urlencoded::from_bytes(body).map_err(|e| format!("Invalid form body: {}", e))?;
+    let mut data = Map::new();
+    for (key, value) in fields {
+        match data.get_mut(&key) {
+            Some(Value::Array(values)) => values.push(Value::String(value)),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), Value::String(value)]),
+            None => {
+                data.insert(key, Value::String(value));
+            }
+        }
+    }
+
+    let event_field = source.event_path.as_deref().unwrap_or("event");
+    let mut extra = HashMap::new();
+    if let Some(event) = data.get(event_field).filter(|event| event.is_string()) {
+        extra.insert("event".to_string(), event.clone());
+    }
+    Ok(Payload { data: Some(Value::Object(data)), _extra: extra })
+}
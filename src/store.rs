@@ -0,0 +1,93 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::log_msg;
+
+/// Root directory for everything the bridge persists (`DATA_DIR`, default `data`).
+pub fn data_dir() -> PathBuf {
+    PathBuf::from(env::var("DATA_DIR").unwrap_or_else(|_| "data".to_string()))
+}
+
+/// Durably writes `value` as `<dir>/<id>.json`, replacing any previous version.
+pub fn write_json<T: Serialize>(dir: &Path, id: &str, value: &T) -> io::Result<()> {
+    create_dir_all(dir)?;
+    let json = serde_json::to_vec_pretty(value)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    // Write to a temp file and rename so a crash never leaves a torn entry.
+    let tmp_path = dir.join(format!("{}.tmp", id));
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&tmp_path)?;
+    file.write_all(&json)?;
+    file.sync_all()?;
+    rename(&tmp_path, json_path(dir, id))
+}
+
+/// Reads `<dir>/<id>.json`, or `None` if it doesn't exist or can't be parsed.
+pub fn read_json<T: DeserializeOwned>(dir: &Path, id: &str) -> Option<T> {
+    let content = read_to_string(json_path(dir, id)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Removes `<dir>/<id>.json`. Returns false if there was nothing to remove.
+pub fn remove_json(dir: &Path, id: &str) -> bool {
+    match remove_file(json_path(dir, id)) {
+        Ok(_) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => {
+            log_msg(&format!("Failed to remove {}: {}", json_path(dir, id).display(), e), "⚠️");
+            false
+        }
+    }
+}
+
+/// Every entry in `dir`, ordered by id. Unreadable entries are logged and skipped.
+pub fn list_json<T: DeserializeOwned>(dir: &Path) -> Vec<T> {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json"))
+        .collect();
+    paths.sort();
+
+    let mut values = Vec::new();
+    for path in paths {
+        let parsed = read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| serde_json::from_str::<T>(&content).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(value) => values.push(value),
+            Err(e) => log_msg(&format!("Skipping unreadable entry {}: {}", path.display(), e), "⚠️"),
+        }
+    }
+    values
+}
+
+/// Ids are used as file names, so only accept the characters we generate.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// A file-name-safe id derived from arbitrary (possibly untrusted) input.
+pub fn hashed_id(value: &str) -> String {
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn json_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
@@ -0,0 +1,341 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+/// A value transform applied to a mapped field. In mapping files a bare
+/// string names a transform that takes no options, e.g. `"trim"`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "fn", rename_all = "snake_case")]
+pub enum Transform {
+    Trim,
+    Lowercase,
+    Uppercase,
+    /// Capitalises the first letter of every word.
+    Titlecase,
+    /// Joins the current value with the values at `paths`, skipping blanks.
+    Concat {
+        paths: Vec<String>,
+        #[serde(default = "default_separator")]
+        separator: String,
+    },
+    /// Picks the first or last name out of a full name ("Jane Q Roofer" or "Roofer, Jane").
+    SplitName { part: NamePart },
+    /// Shorthand for `split_name` with `part: first`.
+    FirstName,
+    /// Shorthand for `split_name` with `part: last`.
+    LastName,
+    /// Re-formats a date or timestamp. `to` is `unix` (the default, as JobNimbus
+    /// expects), `rfc3339` or a strftime format. Inputs without an offset are
+    /// read in `tz`, which is also the zone the output is rendered in.
+    Date {
+        #[serde(default)]
+        from: Option<String>,
+        #[serde(default = "default_date_format")]
+        to: String,
+        #[serde(default)]
+        tz: Option<String>,
+    },
+    /// Parses "$1,250.00" style text into a number.
+    Number,
+    /// Like `number` but rejects fractions.
+    Integer,
+    /// Normalises a phone number to E.164, assuming `country_code` when the
+    /// number doesn't carry one.
+    PhoneE164 {
+        #[serde(default = "default_country_code")]
+        country_code: String,
+    },
+    /// Translates a value through a table, e.g. SCH stage "Sold" to JN status
+    /// "Job Sold". Unknown values use `default`, or fail when there is none.
+    Lookup {
+        table: BTreeMap<String, Value>,
+        #[serde(default)]
+        default: Option<Value>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NamePart {
+    First,
+    Last,
+}
+
+/// Either form a transform can take in a mapping file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TransformSpec {
+    Name(String),
+    Full(Transform),
+}
+
+impl TransformSpec {
+    pub fn resolve(&self) -> Result<Transform, String> {
+        match self {
+            TransformSpec::Full(transform) => Ok(transform.clone()),
+            TransformSpec::Name(name) => serde_json::from_value(serde_json::json!({ "fn": name }))
+                .map_err(|_| format!("Unknown transform '{}' (or it needs options)", name)),
+        }
+    }
+}
+
+fn default_separator() -> String {
+    " ".to_string()
+}
+
+fn default_date_format() -> String {
+    "unix".to_string()
+}
+
+fn default_country_code() -> String {
+    "1".to_string()
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tagged = serde_json::to_value(self).unwrap_or(Value::Null);
+        f.write_str(tagged.get("fn").and_then(Value::as_str).unwrap_or("transform"))
+    }
+}
+
+impl Transform {
+    /// Checks options that can only be wrong in ways serde doesn't see, such
+    /// as strftime formats and time zone names.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Transform::Date { from, to, tz } = self {
+            if let Some(from) = from {
+                check_strftime("from", from)?;
+            }
+            if to != "unix" && to != "rfc3339" {
+                check_strftime("to", to)?;
+            }
+            if let Some(name) = tz {
+                name.parse::<Tz>().map_err(|_| format!("Unknown time zone '{}'", name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the transform. `lookup` resolves extra paths for `concat`.
+    /// Missing values pass through untouched except for `concat`, and arrays
+    /// (from `[*]` paths) are transformed element by element.
+    pub fn apply(&self, value: Option<Value>, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Option<Value>, String> {
+        if let Transform::Concat { paths, separator } = self {
+            let mut parts: Vec<String> = value.iter().filter_map(text).collect();
+            parts.extend(paths.iter().filter_map(|p| lookup(p)).filter_map(|v| text(&v)));
+            let parts: Vec<String> = parts.into_iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+            return Ok((!parts.is_empty()).then(|| Value::from(parts.join(separator))));
+        }
+
+        match value {
+            None | Some(Value::Null) => Ok(value),
+            Some(Value::Array(items)) => items.into_iter()
+                .map(|item| self.apply_one(item))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|items| Some(Value::Array(items))),
+            Some(value) => self.apply_one(value).map(Some),
+        }
+    }
+
+    fn apply_one(&self, value: Value) -> Result<Value, String> {
+        match self {
+            Transform::Trim => map_text(&value, |s| s.trim().to_string()),
+            Transform::Lowercase => map_text(&value, |s| s.to_lowercase()),
+            Transform::Uppercase => map_text(&value, |s| s.to_uppercase()),
+            Transform::Titlecase => map_text(&value, titlecase),
+            Transform::SplitName { part } => map_text(&value, |s| split_name(s, *part)),
+            Transform::FirstName => map_text(&value, |s| split_name(s, NamePart::First)),
+            Transform::LastName => map_text(&value, |s| split_name(s, NamePart::Last)),
+            Transform::Date { from, to, tz } => reformat_date(&value, from.as_deref(), to, tz.as_deref()),
+            Transform::Number => parse_number(&value).map(number_value),
+            Transform::Integer => {
+                let n = parse_number(&value)?;
+                if n.fract() != 0.0 {
+                    return Err(format!("'{}' is not a whole number", n));
+                }
+                Ok(Value::from(n as i64))
+            }
+            Transform::PhoneE164 { country_code } => {
+                let raw = require_text(&value)?;
+                to_e164(&raw, country_code).map(Value::from)
+            }
+            Transform::Lookup { table, default } => {
+                let key = require_text(&value)?;
+                table.get(&key)
+                    .or_else(|| table.iter().find(|(k, _)| k.trim().eq_ignore_ascii_case(key.trim())).map(|(_, v)| v))
+                    .or(default.as_ref())
+                    .cloned()
+                    .ok_or_else(|| format!("No lookup entry for '{}'", key))
+            }
+            Transform::Concat { .. } => Ok(value),
+        }
+    }
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn require_text(value: &Value) -> Result<String, String> {
+    text(value).ok_or_else(|| format!("Expected text but found {}", value))
+}
+
+fn map_text(value: &Value, f: impl Fn(&str) -> String) -> Result<Value, String> {
+    require_text(value).map(|s| Value::from(f(&s)))
+}
+
+fn titlecase(s: &str) -> String {
+    s.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn split_name(full: &str, part: NamePart) -> String {
+    // "Roofer, Jane" is last name first.
+    if let Some((last, first)) = full.split_once(',') {
+        return match part {
+            NamePart::First => first.split_whitespace().next().unwrap_or("").to_string(),
+            NamePart::Last => last.trim().to_string(),
+        };
+    }
+    let words: Vec<&str> = full.split_whitespace().collect();
+    match (part, words.split_first()) {
+        (NamePart::First, Some((first, _))) => first.to_string(),
+        (NamePart::Last, Some((_, rest))) => rest.last().copied().unwrap_or("").to_string(),
+        (_, None) => String::new(),
+    }
+}
+
+fn parse_number(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) => n.as_f64().ok_or_else(|| format!("'{}' is not a number", n)),
+        Value::String(s) => {
+            let cleaned: String = s.trim()
+                .chars()
+                .filter(|c| !matches!(c, '$' | ',' | ' ' | '%'))
+                .collect();
+            cleaned.parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("'{}' is not a number", s))
+        }
+        other => Err(format!("Expected a number but found {}", other)),
+    }
+}
+
+fn number_value(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        Value::from(n)
+    }
+}
+
+fn to_e164(raw: &str, country_code: &str) -> Result<String, String> {
+    let digits: String = raw.chars().filter(char::is_ascii_digit).collect();
+    let country_code = country_code.trim_start_matches('+');
+    let number = if raw.trim_start().starts_with('+') {
+        digits
+    } else if let Some(international) = digits.strip_prefix("00") {
+        international.to_string()
+    } else if country_code == "1" && digits.len() == 11 && digits.starts_with('1') {
+        digits
+    } else if country_code == "1" && digits.len() != 10 {
+        return Err(format!("'{}' is not a valid phone number", raw));
+    } else {
+        format!("{}{}", country_code, digits.trim_start_matches('0'))
+    };
+
+    if (8..=15).contains(&number.len()) {
+        Ok(format!("+{}", number))
+    } else {
+        Err(format!("'{}' is not a valid phone number", raw))
+    }
+}
+
+fn reformat_date(value: &Value, from: Option<&str>, to: &str, tz: Option<&str>) -> Result<Value, String> {
+    let tz: Tz = match tz {
+        Some(name) => name.parse().map_err(|_| format!("Unknown time zone '{}'", name))?,
+        None => Tz::UTC,
+    };
+    let instant = parse_date(value, from, tz)?;
+    let local = instant.with_timezone(&tz);
+    Ok(match to {
+        "unix" => Value::from(instant.timestamp()),
+        "rfc3339" => Value::from(local.to_rfc3339()),
+        format => {
+            let mut out = String::new();
+            write!(out, "{}", local.format(format)).map_err(|_| format!("Invalid date format '{}'", format))?;
+            Value::from(out)
+        }
+    })
+}
+
+fn check_strftime(option: &str, format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("'{}' is not a valid strftime format for '{}'", format, option));
+    }
+    Ok(())
+}
+
+fn parse_date(value: &Value, from: Option<&str>, tz: Tz) -> Result<DateTime<Utc>, String> {
+    if let Value::Number(n) = value {
+        let secs = n.as_i64().ok_or_else(|| format!("'{}' is not a timestamp", n))?;
+        return Utc.timestamp_opt(secs, 0).single().ok_or_else(|| format!("'{}' is not a timestamp", n));
+    }
+    let raw = require_text(value)?;
+    let raw = raw.trim();
+    let in_zone = |naive: NaiveDateTime| {
+        tz.from_local_datetime(&naive)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok_or_else(|| format!("'{}' does not exist in {}", raw, tz))
+    };
+
+    if let Some(format) = from {
+        if let Ok(dt) = DateTime::parse_from_str(raw, format) {
+            return Ok(dt.with_timezone(&Utc));
+        }
+        if let Ok(naive) = NaiveDateTime::parse_from_str(raw, format) {
+            return in_zone(naive);
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(raw, format) {
+            return in_zone(date.and_hms_opt(0, 0, 0).unwrap());
+        }
+        return Err(format!("'{}' does not match date format '{}'", raw, format));
+    }
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%m/%d/%Y %H:%M", "%m/%d/%Y %I:%M %p"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(raw, format) {
+            return in_zone(naive);
+        }
+    }
+    for format in ["%Y-%m-%d", "%m/%d/%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(raw, format) {
+            return in_zone(date.and_hms_opt(0, 0, 0).unwrap());
+        }
+    }
+    if let Ok(secs) = raw.parse::<i64>() {
+        return parse_date(&Value::from(secs), None, tz);
+    }
+    Err(format!("'{}' is not a recognised date", raw))
+}
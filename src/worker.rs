@@ -0,0 +1,44 @@
+use actix_web::rt::time::sleep;
+use reqwest::Client;
+use std::env;
+use std::time::Duration;
+
+use crate::delivery::{self, Outcome};
+use crate::history::{self, DeliveryState};
+use crate::idempotency;
+use crate::log_msg;
+use crate::queue;
+
+/// Drains the on-disk queue forever. The first pass runs immediately so
+/// deliveries left over from a previous run are resumed on startup.
+pub async fn run(client: Client) {
+    let interval_secs: u64 = env::var("QUEUE_POLL_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5);
+
+    loop {
+        drain(&client).await;
+        idempotency::prune();
+        history::prune();
+        sleep(Duration::from_secs(interval_secs.max(1))).await;
+    }
+}
+
+async fn drain(client: &Client) {
+    for mut delivery in queue::pending() {
+        if !delivery.is_due() || !queue::claim(&delivery.id) {
+            continue;
+        }
+        if env::var("TEST_MODE").unwrap_or_default() == "true" {
+            queue::complete(&delivery.id);
+            history::record(&delivery, DeliveryState::Succeeded, None, None);
+            log_msg(&format!("Simulated forwarding of delivery {} in test mode.", delivery.id), "🧪");
+            continue;
+        }
+
+        if let Outcome::Delivered(response) = delivery::attempt(client, &mut delivery).await {
+            log_msg(&format!("Response: {}", response.body), "📬");
+        }
+    }
+}
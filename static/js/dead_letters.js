@@ -0,0 +1,213 @@
+/* dead_letters.js */
+(function() {
+  function escapeHtml(text) {
+    return String(text)
+      .replace(/&/g, '&amp;')
+      .replace(/</g, '&lt;')
+      .replace(/>/g, '&gt;')
+      .replace(/"/g, '&quot;');
+  }
+
+  function prettyBody(body) {
+    try {
+      return JSON.stringify(JSON.parse(body), null, 2);
+    } catch (e) {
+      return body;
+    }
+  }
+
+  function notify(message, kind) {
+    const notificationElement = document.createElement('div');
+    notificationElement.className = `notification ${kind}`;
+    notificationElement.innerHTML = message;
+    document.querySelector('.logs-container').appendChild(notificationElement);
+
+    setTimeout(() => {
+      notificationElement.style.opacity = '0';
+      setTimeout(() => notificationElement.remove(), 500);
+    }, 3000);
+  }
+
+  window.fetchDeadLetters = function() {
+    const content = document.getElementById('dead-letters-content');
+    content.innerHTML = '<div style="text-align: center; padding: 20px;">Loading dead letters...</div>';
+
+    fetch('/dead_letters')
+      .then(response => response.json())
+      .then(letters => {
+        if (letters.length === 0) {
+          content.innerHTML = '<div class="dead-letters-empty"><p>No failed deliveries. 🎉</p></div>';
+          return;
+        }
+
+        let html = `
+          <table id="dead-letters-table">
+            <thead>
+              <tr>
+                <th>Delivery</th>
+                <th>Failed</th>
+                <th>Status</th>
+                <th>Reason</th>
+                <th></th>
+              </tr>
+            </thead>
+            <tbody>
+        `;
+
+        letters.forEach(letter => {
+          html += `
+            <tr>
+              <td>${escapeHtml(letter.id)}</td>
+              <td>${new Date(letter.failed_at).toLocaleString()}</td>
+              <td>${letter.upstream_status ? `<span class="status-${String(letter.upstream_status)[0]}xx">${letter.upstream_status}</span>` : '—'}</td>
+              <td>${escapeHtml(letter.reason)}</td>
+              <td><button class="dead-letter-btn" onclick="inspectDeadLetter('${escapeHtml(letter.id)}')">🔍</button></td>
+            </tr>
+          `;
+        });
+
+        html += '</tbody></table>';
+        content.innerHTML = html;
+      })
+      .catch(err => {
+        console.error('Error fetching dead letters:', err);
+        content.innerHTML = '<p>Error fetching dead letters</p>';
+      });
+  };
+
+  window.inspectDeadLetter = function(id) {
+    const content = document.getElementById('dead-letters-content');
+
+    fetch(`/dead_letters/${encodeURIComponent(id)}`)
+      .then(response => response.json())
+      .then(letter => {
+        content.innerHTML = `
+          <button class="dead-letter-btn" onclick="fetchDeadLetters()">⬅️ Back</button>
+          <h3>Delivery ${escapeHtml(letter.id)}</h3>
+          <p><strong>Reason:</strong> ${escapeHtml(letter.reason)}</p>
+          <p><strong>Attempts:</strong> ${letter.attempts} &nbsp; <strong>Upstream status:</strong> ${letter.upstream_status || '—'}</p>
+          <h4>Upstream response</h4>
+          <pre class="dead-letter-pre">${escapeHtml(letter.upstream_body ? prettyBody(letter.upstream_body) : '—')}</pre>
+          <h4>Original payload</h4>
+          <pre class="dead-letter-pre">${escapeHtml(JSON.stringify(letter.payload, null, 2))}</pre>
+          <h4>Outbound body</h4>
+          ${letter.unprocessed ? '<p>Not built yet: re-submitting runs the payload through plugins, mappings and scripts again, unless a body is entered here.</p>' : ''}
+          <textarea id="dead-letter-body" class="dead-letter-body" spellcheck="false">${escapeHtml(prettyBody(letter.body))}</textarea>
+          <div class="dead-letter-actions">
+            <button class="dead-letter-btn" onclick="saveDeadLetter('${escapeHtml(letter.id)}')">💾 Save</button>
+            <button class="dead-letter-btn" onclick="replayDeadLetter('${escapeHtml(letter.id)}')">🔄 Re-submit</button>
+            <button class="dead-letter-btn" onclick="discardDeadLetter('${escapeHtml(letter.id)}')">🗑️ Discard</button>
+          </div>
+        `;
+      })
+      .catch(err => {
+        console.error('Error fetching dead letter:', err);
+        content.innerHTML = '<p>Error fetching dead letter</p>';
+      });
+  };
+
+  window.saveDeadLetter = async function(id) {
+    let body;
+    try {
+      body = JSON.parse(document.getElementById('dead-letter-body').value);
+    } catch (e) {
+      notify('<span class="emoji">❌</span> Outbound body is not valid JSON', 'error');
+      return false;
+    }
+
+    const response = await fetch(`/dead_letters/${encodeURIComponent(id)}`, {
+      method: 'PUT',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ body: body })
+    });
+    if (!response.ok) {
+      notify('<span class="emoji">❌</span> Error saving dead letter', 'error');
+      return false;
+    }
+    notify('<span class="emoji">💾</span> Dead letter saved', 'success');
+    return true;
+  };
+
+  window.replayDeadLetter = async function(id) {
+    // Save first so edits made in the textarea are what gets re-submitted.
+    // An empty body is left for the pipeline to build.
+    const edited = document.getElementById('dead-letter-body').value.trim() !== '';
+    if (edited && !(await window.saveDeadLetter(id))) {
+      return;
+    }
+
+    const response = await fetch(`/dead_letters/${encodeURIComponent(id)}/replay`, { method: 'POST' });
+    if (response.ok) {
+      notify('<span class="emoji">🔄</span> Delivery re-submitted', 'success');
+      window.fetchDeadLetters();
+      if (window.fetchLogs) {
+        window.fetchLogs();
+      }
+    } else {
+      notify('<span class="emoji">❌</span> Error re-submitting delivery', 'error');
+    }
+  };
+
+  window.discardDeadLetter = async function(id) {
+    if (!confirm(`Discard delivery ${id}? This cannot be undone.`)) {
+      return;
+    }
+
+    const response = await fetch(`/dead_letters/${encodeURIComponent(id)}`, { method: 'DELETE' });
+    if (response.ok) {
+      notify('<span class="emoji">🗑️</span> Dead letter discarded', 'success');
+      window.fetchDeadLetters();
+    } else {
+      notify('<span class="emoji">❌</span> Error discarding dead letter', 'error');
+    }
+  };
+
+  // Additional styles for the dead letter panel
+  const style = document.createElement('style');
+  style.textContent = `
+    .dead-letter-btn {
+      background: rgba(255, 255, 255, 0.1);
+      border: 1px solid rgba(255, 255, 255, 0.2);
+      border-radius: 4px;
+      color: inherit;
+      cursor: pointer;
+      padding: 4px 10px;
+      margin-right: 6px;
+    }
+
+    .dead-letter-btn:hover {
+      background: rgba(255, 255, 255, 0.2);
+    }
+
+    .dead-letter-pre {
+      background-color: rgba(0, 0, 0, 0.05);
+      border-radius: 4px;
+      padding: 10px;
+      max-height: 200px;
+      overflow: auto;
+      white-space: pre-wrap;
+      font-family: monospace;
+    }
+
+    .dead-letter-body {
+      width: 100%;
+      min-height: 200px;
+      font-family: monospace;
+      background-color: rgba(0, 0, 0, 0.05);
+      color: inherit;
+      border: 1px solid rgba(255, 255, 255, 0.2);
+      border-radius: 4px;
+      padding: 10px;
+    }
+
+    .dead-letter-actions {
+      margin-top: 10px;
+    }
+
+    .dead-letters-empty {
+      text-align: center;
+      padding: 20px;
+    }
+  `;
+  document.head.appendChild(style);
+})();
@@ -19,6 +19,8 @@
       fetchConfig();
     } else if (tabName === 'readme') {
       fetchReadme();
+    } else if (tabName === 'dead-letters' && window.fetchDeadLetters) {
+      window.fetchDeadLetters();
     }
   };
 
@@ -45,6 +47,8 @@
       fetchConfig();
     } else if (tabName === 'readme' && document.getElementById('readme-content').innerHTML.trim() === '') {
       fetchReadme();
+    } else if (tabName === 'dead-letters' && window.fetchDeadLetters) {
+      window.fetchDeadLetters();
     }
   };
 
@@ -75,6 +79,8 @@
       modalTitle.innerHTML = '<span class="emoji">📖</span> Documentation';
     } else if (currentTab === 'test-results') {
       modalTitle.innerHTML = '<span class="emoji">🧪</span> Test Results';
+    } else if (currentTab === 'dead-letters') {
+      modalTitle.innerHTML = '<span class="emoji">📮</span> Dead Letters';
     }
   }
 
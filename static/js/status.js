@@ -0,0 +1,64 @@
+/* status.js */
+(function() {
+  function renderRateLimit(limit) {
+    if (!limit.enabled) {
+      return '<span class="status-item">🪣 Rate limit off</span>';
+    }
+    const tokens = Math.max(0, limit.tokens).toFixed(1);
+    const level = limit.waiting > 0 ? 'status-busy' : (limit.tokens < 1 ? 'status-warn' : 'status-ok');
+    return `<span class="status-item ${level}" title="Job Nimbus calls: ${limit.rate_per_sec}/s, burst ${limit.burst}">` +
+      `🪣 ${tokens}/${limit.burst} tokens · ${limit.waiting} waiting</span>`;
+  }
+
+  function renderCircuit(circuit) {
+    if (!circuit.enabled) {
+      return '<span class="status-item">🔌 Circuit breaker off</span>';
+    }
+    const labels = { closed: 'closed', open: 'open', half_open: 'half-open' };
+    const level = circuit.state === 'closed' ? 'status-ok' : (circuit.state === 'open' ? 'status-busy' : 'status-warn');
+    const retry = circuit.retry_at ? ` · probe ${new Date(circuit.retry_at).toLocaleTimeString()}` : '';
+    return `<span class="status-item ${level}" title="${circuit.consecutive_failures}/${circuit.failure_threshold} consecutive failures">` +
+      `🔌 Job Nimbus circuit ${labels[circuit.state]}${retry}</span>`;
+  }
+
+  window.fetchStatus = function() {
+    const bar = document.getElementById('upstream-status');
+    if (!bar) return;
+
+    fetch('/status')
+      .then(response => response.json())
+      .then(status => {
+        bar.innerHTML = renderCircuit(status.circuit_breaker) +
+          renderRateLimit(status.rate_limit) +
+          `<span class="status-item">💾 ${status.queued} queued</span>`;
+      })
+      .catch(() => {
+        bar.innerHTML = '<span class="status-item status-warn">⚠️ Status unavailable</span>';
+      });
+  };
+
+  document.addEventListener('DOMContentLoaded', function() {
+    window.fetchStatus();
+    setInterval(window.fetchStatus, 5000);
+  });
+
+  const style = document.createElement('style');
+  style.textContent = `
+    .status-bar {
+      display: flex;
+      gap: 15px;
+      padding: 8px 0;
+      font-size: 0.9em;
+      opacity: 0.85;
+    }
+
+    .status-item.status-warn {
+      color: #f0ad4e;
+    }
+
+    .status-item.status-busy {
+      color: #e06c75;
+    }
+  `;
+  document.head.appendChild(style);
+})();
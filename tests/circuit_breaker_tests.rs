@@ -0,0 +1,58 @@
+use actix_web::{web, App};
+use reqwest::Client;
+use sch2jn::circuit_breaker::{self, CircuitState};
+use sch2jn::delivery::{self, Outcome};
+use sch2jn::handlers::{health_handler, Payload};
+use sch2jn::queue::{self, Delivery};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+use std::time::Duration;
+
+// The breaker is global, so its whole life cycle is walked through in one test.
+#[actix_web::test]
+async fn test_breaker_opens_probes_and_closes() {
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/circuit_breaker_tests");
+    env::set_var("CIRCUIT_FAILURE_THRESHOLD", "2");
+    env::set_var("CIRCUIT_OPEN_SECS", "1");
+
+    circuit_breaker::record_failure();
+    assert_eq!(circuit_breaker::status().state, CircuitState::Closed);
+    circuit_breaker::record_failure();
+    assert_eq!(circuit_breaker::status().state, CircuitState::Open);
+    assert!(!circuit_breaker::allow());
+    assert!(circuit_breaker::retry_in() > Duration::ZERO);
+
+    // While open, deliveries go back on the queue without using an attempt.
+    let mut delivery = Delivery::new(Payload { data: Some(serde_json::json!({})), _extra: HashMap::new() }, "{}".to_string());
+    queue::claim(&delivery.id);
+    let outcome = delivery::attempt(&Client::new(), &mut delivery).await;
+    assert!(matches!(outcome, Outcome::Retrying { .. }));
+    assert_eq!(delivery.attempts, 0);
+    assert!(queue::get(&delivery.id).is_some());
+    queue::complete(&delivery.id);
+
+    let app = actix_web::test::init_service(App::new().route("/health", web::get().to(health_handler))).await;
+    let req = actix_web::test::TestRequest::get().uri("/health").to_request();
+    let health: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["circuit_breaker"]["state"], "open");
+
+    // After the open period one probe is let through; a failed probe re-opens.
+    actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+    assert!(circuit_breaker::allow());
+    assert_eq!(circuit_breaker::status().state, CircuitState::HalfOpen);
+    assert!(!circuit_breaker::allow());
+    circuit_breaker::record_failure();
+    assert_eq!(circuit_breaker::status().state, CircuitState::Open);
+
+    // A successful probe closes it again.
+    actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+    assert!(circuit_breaker::allow());
+    circuit_breaker::record_success();
+    let status = circuit_breaker::status();
+    assert_eq!(status.state, CircuitState::Closed);
+    assert_eq!(status.consecutive_failures, 0);
+    assert!(circuit_breaker::allow());
+}
@@ -0,0 +1,152 @@
+use actix_web::{web, App};
+use sch2jn::coalesce::{self, deep_merge};
+use sch2jn::handlers::{delivery_status_handler, post_handler, Payload};
+use sch2jn::http_client;
+use sch2jn::plugin::{PluginOutcome, PluginRun};
+use sch2jn::queue::{self, Delivery};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{create_dir_all, remove_dir_all};
+
+// The tests share one queue directory, which the first one clears.
+static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// An update to record `id`, routed by `rule` with one plugin run.
+fn routed_update(id: &str, data: serde_json::Value, rule: &str, plugin: &str) -> Delivery {
+    let mut data = data;
+    data["id"] = serde_json::json!(id);
+    let mut delivery = Delivery::new(Payload { data: Some(data.clone()), _extra: HashMap::new() }, data.to_string());
+    delivery.rule = Some(rule.to_string());
+    delivery.tags = vec![rule.to_string()];
+    delivery.plugins = vec![PluginRun {
+        plugin: plugin.to_string(),
+        outcome: PluginOutcome::Passed,
+        elapsed_ms: 0.0,
+        fuel_used: 0,
+        error: None,
+    }];
+    delivery
+}
+
+fn use_queue() {
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/coalesce_tests");
+    env::remove_var("SCH_ID_FIELD");
+}
+
+#[test]
+fn test_deep_merge_latest_wins() {
+    let mut base = serde_json::json!({ "name": "A", "address": { "city": "Tampa", "zip": "33601" }, "tags": ["x"] });
+    deep_merge(&mut base, serde_json::json!({ "name": "B", "address": { "zip": "33602" }, "tags": ["y"], "email": "b@example.com" }));
+    assert_eq!(base, serde_json::json!({
+        "name": "B",
+        "address": { "city": "Tampa", "zip": "33602" },
+        "tags": ["y"],
+        "email": "b@example.com"
+    }));
+}
+
+#[actix_web::test]
+async fn test_rapid_updates_are_merged() {
+    let _env = ENV_LOCK.lock().await;
+    use_queue();
+    // A leftover delivery from an aborted run would swallow the first update.
+    let _ = remove_dir_all("target/test-data/coalesce_tests/queue");
+    env::set_var("TEST_MODE", "true");
+    env::set_var("COALESCE_WINDOW_SECS", "60");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/deliveries/{id}", web::get().to(delivery_status_handler)),
+    )
+    .await;
+    let post = |data: serde_json::Value| {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": "customer.updated", "data": data }))
+            .to_request();
+        actix_web::test::call_and_read_body_json::<_, _, serde_json::Value>(&app, req)
+    };
+
+    let first = post(serde_json::json!({ "id": "cust-7", "first_name": "Jan", "email": "jan@example.com" })).await;
+    assert!(first["message"].as_str().unwrap().starts_with("Held"));
+    let id = first["delivery_id"].as_str().unwrap().to_string();
+
+    let second = post(serde_json::json!({ "id": "cust-7", "first_name": "Jane" })).await;
+    let third = post(serde_json::json!({ "id": "cust-7", "last_name": "Roofer" })).await;
+    assert_eq!(second["delivery_id"], id.as_str());
+    assert_eq!(third["delivery_id"], id.as_str());
+
+    // Another record gets its own delivery.
+    let other = post(serde_json::json!({ "id": "cust-8", "first_name": "Sam" })).await;
+    assert_ne!(other["delivery_id"], id.as_str());
+
+    // Without an SCH id updates aren't held or merged, even for the same email.
+    let by_email = post(serde_json::json!({ "email": "pat@example.com", "first_name": "Pat" })).await;
+    let again = post(serde_json::json!({ "email": "pat@example.com", "last_name": "Lee" })).await;
+    assert_eq!(by_email["status"], "ok");
+    assert_eq!(again["status"], "ok");
+    assert_ne!(by_email["delivery_id"], again["delivery_id"]);
+
+    let pending = queue::get(&id).unwrap();
+    let body: serde_json::Value = serde_json::from_str(&pending.body).unwrap();
+    assert_eq!(body, serde_json::json!({
+        "id": "cust-7", "first_name": "Jane", "last_name": "Roofer", "email": "jan@example.com"
+    }));
+    assert_eq!(pending.merged.len(), 2);
+
+    let req = actix_web::test::TestRequest::get().uri(&format!("/deliveries/{}", pending.merged[0])).to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "merged");
+    assert_eq!(status["merged_into"], id.as_str());
+
+    let req = actix_web::test::TestRequest::get().uri(&format!("/deliveries/{}", id)).to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "queued");
+    assert_eq!(status["merged"].as_array().unwrap().len(), 2);
+
+    queue::complete(&id);
+    queue::complete(other["delivery_id"].as_str().unwrap());
+}
+
+#[actix_web::test]
+async fn test_updates_routed_alike_keep_their_routing_when_merged() {
+    let _env = ENV_LOCK.lock().await;
+    use_queue();
+
+    let first = routed_update("cust-20", serde_json::json!({ "first_name": "Jan" }), "vip", "enrich");
+    assert!(coalesce::merge_or_enqueue(&first).unwrap().is_none());
+    let second = routed_update("cust-20", serde_json::json!({ "last_name": "Roofer" }), "vip", "score");
+    let merged = coalesce::merge_or_enqueue(&second).unwrap().unwrap();
+    assert_eq!(merged.id, first.id);
+
+    let pending = queue::get(&first.id).unwrap();
+    assert_eq!(pending.rule.as_deref(), Some("vip"));
+    assert_eq!(pending.tags, vec!["vip"]);
+    let plugins: Vec<&str> = pending.plugins.iter().map(|run| run.plugin.as_str()).collect();
+    assert_eq!(plugins, vec!["enrich", "score"]);
+    assert_eq!(pending.merged, vec![second.id.clone()]);
+    queue::complete(&first.id);
+}
+
+#[actix_web::test]
+async fn test_updates_routed_by_another_rule_are_not_merged() {
+    let _env = ENV_LOCK.lock().await;
+    use_queue();
+
+    let first = routed_update("cust-21", serde_json::json!({ "first_name": "Jan" }), "vip", "enrich");
+    assert!(coalesce::merge_or_enqueue(&first).unwrap().is_none());
+    let second = routed_update("cust-21", serde_json::json!({ "last_name": "Roofer" }), "standard", "score");
+    assert!(coalesce::merge_or_enqueue(&second).unwrap().is_none());
+
+    // Each keeps its own rule, tags and plugin runs.
+    assert!(queue::get(&first.id).unwrap().merged.is_empty());
+    let queued = queue::get(&second.id).unwrap();
+    assert_eq!(queued.rule.as_deref(), Some("standard"));
+    assert_eq!(queued.tags, vec!["standard"]);
+    assert_eq!(queued.plugins[0].plugin, "score");
+    queue::complete(&first.id);
+    queue::complete(&second.id);
+}
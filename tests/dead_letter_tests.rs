@@ -0,0 +1,98 @@
+use actix_web::{test, web, App};
+use sch2jn::dead_letter;
+use sch2jn::handlers::{
+    dead_letter_handler, dead_letters_handler, discard_dead_letter_handler, edit_dead_letter_handler,
+    replay_dead_letter_handler, Payload,
+};
+use sch2jn::{http_client, idempotency};
+use sch2jn::jobnimbus::UpstreamResponse;
+use sch2jn::queue::{self, Delivery};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+
+fn park_sample(first_name: &str) -> Delivery {
+    env::set_var("DATA_DIR", "target/test-data/dead_letter_tests");
+    let _ = create_dir_all("logs");
+    let mut delivery = Delivery::new(
+        Payload {
+            data: Some(serde_json::json!({ "first_name": first_name })),
+            _extra: HashMap::new(),
+        },
+        serde_json::json!({ "first_name": first_name }).to_string(),
+    );
+    delivery.attempts = 1;
+    let response = UpstreamResponse {
+        status: 400,
+        body: "{\"error\":\"display_name is required\"}".to_string(),
+        retry_after: None,
+        limited: false,
+    };
+    dead_letter::park(&delivery, "Job Nimbus rejected the delivery (HTTP 400)", Some(&response));
+    delivery
+}
+
+#[actix_web::test]
+async fn test_dead_letter_edit_and_replay() {
+    let delivery = park_sample("replayed");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/dead_letters", web::get().to(dead_letters_handler))
+            .route("/dead_letters/{id}", web::get().to(dead_letter_handler))
+            .route("/dead_letters/{id}", web::put().to(edit_dead_letter_handler))
+            .route("/dead_letters/{id}/replay", web::post().to(replay_dead_letter_handler)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/dead_letters").to_request();
+    let listed: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(listed.iter().any(|l| l["id"] == delivery.id.as_str() && l["upstream_status"] == 400));
+
+    let edited = serde_json::json!({ "first_name": "replayed", "display_name": "Replayed Customer" });
+    let req = test::TestRequest::put()
+        .uri(&format!("/dead_letters/{}", delivery.id))
+        .set_json(serde_json::json!({ "body": edited }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/dead_letters/{}/replay", delivery.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 202);
+
+    let requeued = queue::pending()
+        .into_iter()
+        .find(|d| d.id == delivery.id)
+        .expect("replayed delivery should be queued");
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&requeued.body).unwrap(), edited);
+    assert_eq!(requeued.attempts, 0);
+    assert!(dead_letter::get(&delivery.id).is_none());
+    queue::complete(&delivery.id);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/dead_letters/{}", delivery.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_dead_letter_discard() {
+    let delivery = park_sample("discarded");
+    let key = format!("discard-{}", delivery.id);
+    idempotency::remember(&key, &delivery.id, 422, "{}");
+    let app = test::init_service(
+        App::new().route("/dead_letters/{id}", web::delete().to(discard_dead_letter_handler)),
+    )
+    .await;
+
+    let uri = format!("/dead_letters/{}", delivery.id);
+    let req = test::TestRequest::delete().uri(&uri).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert!(dead_letter::get(&delivery.id).is_none());
+    // A resend of the discarded delivery isn't answered from the store.
+    assert!(idempotency::lookup(&key).is_none());
+
+    let req = test::TestRequest::delete().uri(&uri).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
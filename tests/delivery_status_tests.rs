@@ -0,0 +1,77 @@
+use actix_web::{web, App};
+use sch2jn::handlers::{delivery_status_handler, post_handler, Payload};
+use sch2jn::http_client;
+use sch2jn::queue::{self, Delivery};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+use std::time::Duration;
+
+fn setup() {
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/delivery_status_tests");
+    env::set_var("TEST_MODE", "true");
+    env::set_var("ACCEPT_MODE", "async");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+}
+
+#[actix_web::test]
+async fn test_async_mode_accepts_then_reports_outcome() {
+    setup();
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/deliveries/{id}", web::get().to(delivery_status_handler)),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "customer.created", "data": { "first_name": "Async" } }))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+    let location = resp.headers().get("Location").unwrap().to_str().unwrap().to_string();
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "queued");
+    let id = body["delivery_id"].as_str().unwrap().to_string();
+    assert_eq!(location, format!("/deliveries/{}", id));
+
+    let mut status = serde_json::Value::Null;
+    for _ in 0..100 {
+        let req = actix_web::test::TestRequest::get().uri(&location).to_request();
+        status = actix_web::test::call_and_read_body_json(&app, req).await;
+        if status["status"] == "succeeded" {
+            break;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(status["status"], "succeeded");
+    assert_eq!(status["id"], id.as_str());
+    assert!(queue::get(&id).is_none());
+}
+
+#[actix_web::test]
+async fn test_queued_and_unknown_deliveries() {
+    setup();
+    let app = actix_web::test::init_service(
+        App::new().route("/deliveries/{id}", web::get().to(delivery_status_handler)),
+    )
+    .await;
+
+    let mut delivery = Delivery::new(Payload { data: Some(serde_json::json!({})), _extra: HashMap::new() }, "{}".to_string());
+    delivery.attempts = 2;
+    delivery.last_error = Some("Job Nimbus answered HTTP 503".to_string());
+    queue::persist(&delivery).unwrap();
+
+    let req = actix_web::test::TestRequest::get().uri(&format!("/deliveries/{}", delivery.id)).to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "queued");
+    assert_eq!(status["attempts"], 2);
+    assert_eq!(status["error"], "Job Nimbus answered HTTP 503");
+    queue::complete(&delivery.id);
+
+    let req = actix_web::test::TestRequest::get().uri("/deliveries/no-such-delivery").to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
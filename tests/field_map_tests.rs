@@ -0,0 +1,132 @@
+use actix_web::{web, App};
+use sch2jn::field_map::{self, resolve};
+use sch2jn::handlers::{post_handler, Payload};
+use sch2jn::http_client;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{create_dir_all, write};
+use std::sync::Once;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let _ = create_dir_all("logs");
+        create_dir_all("target/test-data/field_map_tests").unwrap();
+        write(
+            "target/test-data/field_map_tests/mappings.json",
+            serde_json::json!({
+                "mappings": [
+                    {
+                        "event": "customer.*",
+                        "fields": {
+                            "display_name": { "path": "customer.name", "required": true },
+                            "mobile_phone": "customer.phones[0].number",
+                            "tags": "customer.labels[*].name",
+                            "record_type_name": { "const": "Customer" },
+                            "status_name": { "path": "customer.stage", "default": "Lead" },
+                            "address.city": "customer.city",
+                            "source_name": "$.event"
+                        }
+                    },
+                    {
+                        "event": "lead.*",
+                        "fields": {
+                            "display_name": { "path": "first", "transforms": [{ "fn": "concat", "paths": ["last"] }, "titlecase"] },
+                            "mobile_phone": { "path": "phone", "transforms": ["phone_e164"] },
+                            "status_name": { "path": "stage", "transforms": [{ "fn": "lookup", "table": { "Sold": "Job Sold" } }] }
+                        }
+                    }
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        env::set_var("MAPPINGS_FILE", "target/test-data/field_map_tests/mappings.json");
+        env::set_var("DATA_DIR", "target/test-data/field_map_tests");
+        env::set_var("TEST_MODE", "true");
+    });
+}
+
+fn payload(event: &str, data: serde_json::Value) -> Payload {
+    let mut extra = HashMap::new();
+    extra.insert("event".to_string(), serde_json::json!(event));
+    Payload { data: Some(data), _extra: extra }
+}
+
+#[test]
+fn test_resolve_paths() {
+    let data = serde_json::json!({
+        "a": { "b": [ { "c": 1, "d": [ { "e": "x" } ] }, { "c": 2, "d": [ { "e": "y" }, { "e": "z" } ] } ] }
+    });
+    assert_eq!(resolve(&data, "a.b[1].c"), Some(serde_json::json!(2)));
+    assert_eq!(resolve(&data, "a.b[*].c"), Some(serde_json::json!([1, 2])));
+    assert_eq!(resolve(&data, "a.b[*].d[*].e"), Some(serde_json::json!(["x", "y", "z"])));
+    assert_eq!(resolve(&data, "a.missing"), None);
+    assert_eq!(resolve(&data, "a.b[5]"), None);
+}
+
+#[test]
+fn test_mapping_builds_outbound_object() {
+    setup();
+    let inbound = payload("customer.updated", serde_json::json!({
+        "customer": {
+            "name": "Jane Roofer",
+            "phones": [ { "number": "555-0100" } ],
+            "labels": [ { "name": "vip" }, { "name": "storm" } ],
+            "city": "Tampa"
+        }
+    }));
+
+    let outbound = field_map::apply(&inbound).expect("mapping should succeed");
+    assert_eq!(outbound, serde_json::json!({
+        "display_name": "Jane Roofer",
+        "mobile_phone": "555-0100",
+        "tags": ["vip", "storm"],
+        "record_type_name": "Customer",
+        "status_name": "Lead",
+        "address": { "city": "Tampa" },
+        "source_name": "customer.updated"
+    }));
+
+    // Unmapped events are forwarded verbatim.
+    let other = payload("project.updated", serde_json::json!({ "name": "Roof" }));
+    assert_eq!(field_map::apply(&other).unwrap(), serde_json::json!({ "name": "Roof" }));
+}
+
+#[actix_web::test]
+async fn test_missing_required_field_is_rejected() {
+    setup();
+    let app = actix_web::test::init_service(App::new().app_data(web::Data::new(http_client::build().unwrap())).route("/", web::post().to(post_handler))).await;
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "customer.created", "data": { "customer": {} } }))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["fields"][0]["field"], "display_name");
+}
+
+#[actix_web::test]
+async fn test_transform_errors_are_rejected() {
+    setup();
+    let inbound = payload("lead.created", serde_json::json!({
+        "first": " jane ", "last": "ROOFER", "phone": "(727) 555-0100", "stage": "Sold"
+    }));
+    assert_eq!(field_map::apply(&inbound).unwrap(), serde_json::json!({
+        "display_name": "Jane Roofer",
+        "mobile_phone": "+17275550100",
+        "status_name": "Job Sold"
+    }));
+
+    let app = actix_web::test::init_service(App::new().app_data(web::Data::new(http_client::build().unwrap())).route("/", web::post().to(post_handler))).await;
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "lead.created", "data": { "first": "Jane", "phone": "555", "stage": "Sold" } }))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["fields"][0]["field"], "mobile_phone");
+}
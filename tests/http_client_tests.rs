@@ -0,0 +1,18 @@
+use sch2jn::http_client;
+use std::env;
+
+#[test]
+fn test_client_configuration() {
+    env::set_var("HTTP_REQUEST_TIMEOUT_SECS", "5");
+    env::set_var("OUTBOUND_PROXY", "http://proxy.internal:3128");
+    env::set_var("OUTBOUND_NO_PROXY", "localhost,127.0.0.1");
+    assert!(http_client::build().is_ok());
+
+    env::set_var("OUTBOUND_PROXY", "not a url");
+    assert!(http_client::build().unwrap_err().contains("OUTBOUND_PROXY"));
+    env::remove_var("OUTBOUND_PROXY");
+
+    env::set_var("HTTP_CA_BUNDLE", "target/test-data/no-such-bundle.pem");
+    assert!(http_client::build().unwrap_err().contains("HTTP_CA_BUNDLE"));
+    env::remove_var("HTTP_CA_BUNDLE");
+}
@@ -0,0 +1,90 @@
+use actix_web::{test, web, App};
+use chrono::Local;
+use sch2jn::handlers::post_handler;
+use sch2jn::http_client;
+use sch2jn::{idempotency, metrics};
+use std::env;
+use std::fs::create_dir_all;
+
+fn setup() {
+    env::set_var("DATA_DIR", "target/test-data/idempotency_tests");
+    env::set_var("TEST_MODE", "true");
+    let _ = create_dir_all("logs");
+}
+
+fn unique_payload(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "event": "contact.created",
+        "data": {
+            "first_name": name,
+            "nonce": Local::now().to_rfc3339(),
+        }
+    })
+}
+
+#[actix_web::test]
+async fn test_redelivered_payload_is_answered_from_store() {
+    setup();
+    let app = test::init_service(App::new().app_data(web::Data::new(http_client::build().unwrap())).route("/", web::post().to(post_handler))).await;
+    let payload = unique_payload("redelivered");
+    let hits_before = metrics::get("sch2jn_dedupe_hits_total");
+
+    let req = test::TestRequest::post().set_json(&payload).to_request();
+    let first = test::call_service(&app, req).await;
+    assert_eq!(first.status(), 200);
+    assert!(first.headers().get("Idempotent-Replayed").is_none());
+    let first_body = test::read_body(first).await;
+
+    let req = test::TestRequest::post().set_json(&payload).to_request();
+    let second = test::call_service(&app, req).await;
+    assert_eq!(second.status(), 200);
+    assert_eq!(second.headers().get("Idempotent-Replayed").unwrap(), "true");
+    assert_eq!(test::read_body(second).await, first_body);
+    assert!(metrics::get("sch2jn_dedupe_hits_total") > hits_before);
+}
+
+#[actix_web::test]
+async fn test_idempotency_header_takes_precedence() {
+    setup();
+    let app = test::init_service(App::new().app_data(web::Data::new(http_client::build().unwrap())).route("/", web::post().to(post_handler))).await;
+    let delivery_id = format!("sch-{}", Local::now().timestamp_nanos_opt().unwrap());
+
+    // Same delivery id with a different body is still a redelivery.
+    let req = test::TestRequest::post()
+        .insert_header(("Idempotency-Key", delivery_id.as_str()))
+        .set_json(unique_payload("first"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get("Idempotent-Replayed").is_none());
+
+    let req = test::TestRequest::post()
+        .insert_header(("Idempotency-Key", delivery_id.as_str()))
+        .set_json(unique_payload("second"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("Idempotent-Replayed").unwrap(), "true");
+}
+
+#[actix_web::test]
+async fn test_concurrent_duplicates_are_forwarded_once() {
+    setup();
+    let key = format!("claim-{}", Local::now().timestamp_nanos_opt().unwrap());
+    let Ok(claim) = idempotency::claim(&key) else { panic!("a new key can be claimed") };
+    assert!(matches!(idempotency::claim(&key), Err(None)));
+    drop(claim);
+    assert!(idempotency::claim(&key).is_ok());
+
+    let app = test::init_service(App::new().app_data(web::Data::new(http_client::build().unwrap())).route("/", web::post().to(post_handler))).await;
+    let payload = unique_payload("concurrent");
+    let (first, second) = tokio::join!(
+        test::call_service(&app, test::TestRequest::post().set_json(&payload).to_request()),
+        test::call_service(&app, test::TestRequest::post().set_json(&payload).to_request()),
+    );
+    let processed = [&first, &second]
+        .iter()
+        .filter(|resp| resp.status() == 200 && resp.headers().get("Idempotent-Replayed").is_none())
+        .count();
+    assert_eq!(processed, 1);
+    assert!([first.status(), second.status()].contains(&actix_web::http::StatusCode::CONFLICT)
+        || second.headers().get("Idempotent-Replayed").is_some());
+}
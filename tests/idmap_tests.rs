@@ -0,0 +1,43 @@
+use actix_web::{test, web, App};
+use sch2jn::handlers::mappings_handler;
+use sch2jn::idmap;
+use std::env;
+
+fn use_test_data_dir() {
+    env::set_var("DATA_DIR", "target/test-data/idmap_tests");
+}
+
+#[actix_web::test]
+async fn test_mapping_is_queryable_both_ways() {
+    use_test_data_dir();
+    idmap::record("sch/../odd id", "jn-1", Some("delivery-1"));
+    assert_eq!(idmap::lookup_jnid("sch/../odd id").as_deref(), Some("jn-1"));
+    assert_eq!(idmap::by_jnid("jn-1").map(|m| m.sch_id).as_deref(), Some("sch/../odd id"));
+
+    // Re-pointing the SCH record drops the stale reverse entry.
+    idmap::record("sch/../odd id", "jn-2", Some("delivery-2"));
+    assert!(idmap::by_jnid("jn-1").is_none());
+    assert_eq!(idmap::by_jnid("jn-2").and_then(|m| m.delivery_id).as_deref(), Some("delivery-2"));
+
+    idmap::forget("sch/../odd id");
+    assert!(idmap::lookup_jnid("sch/../odd id").is_none());
+    assert!(idmap::by_jnid("jn-2").is_none());
+}
+
+#[actix_web::test]
+async fn test_mappings_endpoint() {
+    use_test_data_dir();
+    idmap::record("sch-77", "jn-77", None);
+    let app = test::init_service(App::new().route("/mappings", web::get().to(mappings_handler))).await;
+
+    let req = test::TestRequest::get().uri("/mappings?sch_id=sch-77").to_request();
+    let mapping: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(mapping["jnid"], "jn-77");
+
+    let req = test::TestRequest::get().uri("/mappings?jnid=jn-77").to_request();
+    let mapping: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(mapping["sch_id"], "sch-77");
+
+    let req = test::TestRequest::get().uri("/mappings?jnid=unknown").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
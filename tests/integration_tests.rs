@@ -1,12 +1,17 @@
 use actix_web::{test, App};
+#[allow(unused_imports)]
 use sch2jn::handlers::{post_handler, logs_handler};
+use sch2jn::http_client;
+#[allow(unused_imports)]
 use chrono::{Local, Duration};
 use std::env;
+#[allow(unused_imports)]
 use std::fs::{remove_file, write, create_dir_all};
 use std::path::Path;
 
 const LOG_FILE_PATH: &str = "logs/log.txt";
 
+#[allow(dead_code)]
 fn clear_log() {
     let _ = remove_file(LOG_FILE_PATH);
 }
@@ -21,7 +26,7 @@ fn ensure_log_directory() {
 #[actix_web::test]
 async fn test_missing_payload() {
     ensure_log_directory();
-    let app = test::init_service(App::new().route("/", actix_web::web::post().to(post_handler))).await;
+    let app = test::init_service(App::new().app_data(actix_web::web::Data::new(http_client::build().unwrap())).route("/", actix_web::web::post().to(post_handler))).await;
     let payload = serde_json::json!({
         "event": "project.updated"
     });
@@ -36,7 +41,7 @@ async fn test_valid_payload_direct_data() {
     env::set_var("TEST_MODE", "true");
     env::set_var("JOB_NIMBUS_API_KEY", "dummy");
 
-    let app = test::init_service(App::new().route("/", actix_web::web::post().to(post_handler))).await;
+    let app = test::init_service(App::new().app_data(actix_web::web::Data::new(http_client::build().unwrap())).route("/", actix_web::web::post().to(post_handler))).await;
     let payload = serde_json::json!({
         "event": "project.updated",
         "data": {
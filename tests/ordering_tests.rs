@@ -0,0 +1,98 @@
+use reqwest::Client;
+use sch2jn::delivery::{self, Outcome};
+use sch2jn::handlers::Payload;
+use sch2jn::history::{self, DeliveryState};
+use sch2jn::ordering::{self, record_key};
+use sch2jn::queue::{self, Delivery};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn setup() {
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/ordering_tests");
+}
+
+fn delivery(data: serde_json::Value) -> Delivery {
+    Delivery::new(Payload { data: Some(data.clone()), _extra: HashMap::new() }, data.to_string())
+}
+
+#[actix_web::test]
+async fn test_record_keys_and_event_times() {
+    setup();
+    assert_eq!(record_key(&delivery(serde_json::json!({ "id": 42 }))).as_deref(), Some("contacts:42"));
+    assert_eq!(
+        record_key(&delivery(serde_json::json!({ "email": " Jane@Example.com " }))).as_deref(),
+        Some("contacts:email:jane@example.com")
+    );
+    assert_eq!(record_key(&delivery(serde_json::json!({ "name": "x" }))), None);
+
+    let at = |value: serde_json::Value| ordering::event_time(&delivery(serde_json::json!({ "updated_at": value })).payload);
+    let expected = chrono::DateTime::parse_from_rfc3339("2024-03-01T12:00:00Z").unwrap();
+    assert_eq!(at(serde_json::json!("2024-03-01T07:00:00-05:00")), Some(expected.into()));
+    assert_eq!(at(serde_json::json!(1709294400)), Some(expected.into()));
+    assert_eq!(at(serde_json::json!(1709294400000i64)), Some(expected.into()));
+    assert_eq!(at(serde_json::json!("yesterday")), None);
+}
+
+#[actix_web::test]
+async fn test_same_record_is_serialized() {
+    setup();
+    let first = ordering::lock("contacts:serial").await;
+    let done = Arc::new(AtomicBool::new(false));
+    let waiter = {
+        let done = done.clone();
+        actix_web::rt::spawn(async move {
+            let _second = ordering::lock("contacts:serial").await;
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+
+    // Other records aren't held up.
+    drop(ordering::lock("contacts:other").await);
+    actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+    assert!(!done.load(Ordering::SeqCst));
+
+    drop(first);
+    waiter.await.unwrap();
+    assert!(done.load(Ordering::SeqCst));
+}
+
+#[actix_web::test]
+async fn test_waits_for_earlier_delivery_and_drops_stale_events() {
+    setup();
+    let client = Client::new();
+
+    // An older delivery for the record is still being retried.
+    let mut earlier = delivery(serde_json::json!({ "id": "ord-1", "updated_at": "2024-03-01T12:00:00Z" }));
+    earlier.attempts = 1;
+    earlier.next_attempt_at = Some(chrono::Local::now() + chrono::Duration::seconds(30));
+    queue::persist(&earlier).unwrap();
+
+    let mut later = delivery(serde_json::json!({ "id": "ord-1", "updated_at": "2024-03-01T12:05:00Z" }));
+    queue::persist(&later).unwrap();
+    queue::claim(&later.id);
+    let outcome = delivery::attempt(&client, &mut later).await;
+    assert!(matches!(outcome, Outcome::Retrying { .. }));
+    assert_eq!(later.attempts, 0);
+    assert!(later.next_attempt_at.unwrap() > chrono::Local::now() + chrono::Duration::seconds(20));
+    queue::complete(&earlier.id);
+    queue::complete(&later.id);
+
+    // A newer update already went out, so this one is stale.
+    env::set_var("STALE_EVENT_POLICY", "drop");
+    ordering::record_delivered("contacts:ord-2", "2024-03-01T13:00:00Z".parse().unwrap(), "newer-delivery");
+    let mut stale = delivery(serde_json::json!({ "id": "ord-2", "updated_at": "2024-03-01T12:00:00Z" }));
+    queue::persist(&stale).unwrap();
+    queue::claim(&stale.id);
+    let outcome = delivery::attempt(&client, &mut stale).await;
+    assert!(matches!(outcome, Outcome::Dropped { .. }));
+    assert!(queue::get(&stale.id).is_none());
+    let status = history::status(&stale.id).unwrap();
+    assert_eq!(status.status, DeliveryState::Dropped);
+    assert!(status.stale);
+    env::remove_var("STALE_EVENT_POLICY");
+}
@@ -0,0 +1,156 @@
+use actix_web::{web, App};
+use sch2jn::handlers::{delivery_status_handler, post_handler, Payload};
+use sch2jn::{dead_letter, http_client};
+use sch2jn::plugin::{self, PluginOutcome, Verdict};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{create_dir_all, remove_dir_all, remove_file, write};
+
+const DIR: &str = "target/test-data/plugin_tests/plugins";
+const REWRITTEN: &str = r#"{"event":"customer.created","data":{"first_name":"Plugged"}}"#;
+
+// A bump allocator is all the host needs from a plugin.
+const ALLOC: &str = r#"
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+"#;
+
+/// Keeps events unless they contain a `!`.
+fn filter_plugin() -> String {
+    format!(
+        r#"(module {}
+  (func (export "filter") (param $ptr i32) (param $len i32) (result i32)
+    (local $end i32)
+    (local.set $end (i32.add (local.get $ptr) (local.get $len)))
+    (block $done
+      (loop $scan
+        (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
+        (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 33))
+          (then (return (i32.const 0))))
+        (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+        (br $scan)))
+    (i32.const 1)))"#,
+        ALLOC
+    )
+}
+
+/// Replaces every event with `REWRITTEN`, logging as it goes.
+fn rewrite_plugin() -> String {
+    format!(
+        r#"(module
+  (import "env" "log" (func $log (param i32 i32)))
+  {}
+  (data (i32.const 0) "{}")
+  (data (i32.const 512) "rewriting")
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (call $log (i32.const 512) (i32.const 9))
+    (i64.const {})))"#,
+        ALLOC,
+        REWRITTEN.replace('"', "\\\""),
+        REWRITTEN.len()
+    )
+}
+
+fn install(name: &str, wat: &str) {
+    write(format!("{}/{}", DIR, name), wat::parse_str(wat).unwrap()).unwrap();
+}
+
+fn payload(first_name: &str) -> Payload {
+    let mut extra = HashMap::new();
+    extra.insert("event".to_string(), serde_json::json!("customer.created"));
+    Payload { data: Some(serde_json::json!({ "first_name": first_name })), _extra: extra }
+}
+
+#[actix_web::test]
+async fn test_plugins_transform_filter_and_fail() {
+    let _ = create_dir_all("logs");
+    let _ = remove_dir_all(DIR);
+    create_dir_all(DIR).unwrap();
+    env::set_var("PLUGINS_DIR", DIR);
+    env::set_var("PLUGIN_FUEL", "100000");
+    env::set_var("DATA_DIR", "target/test-data/plugin_tests");
+    env::set_var("TEST_MODE", "true");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+    install("10-filter.wasm", &filter_plugin());
+    install("20-rewrite.wasm", &rewrite_plugin());
+
+    let mut event = payload("Ann");
+    let (verdict, runs) = plugin::run(&mut event);
+    assert_eq!(verdict, Verdict::Keep);
+    assert_eq!(event.data, Some(serde_json::json!({ "first_name": "Plugged" })));
+    let outcomes: Vec<PluginOutcome> = runs.iter().map(|run| run.outcome).collect();
+    assert_eq!(outcomes, vec![PluginOutcome::Passed, PluginOutcome::Transformed]);
+    assert!(runs.iter().all(|run| run.fuel_used > 0));
+
+    let (verdict, runs) = plugin::run(&mut payload("Hi!"));
+    assert_eq!(verdict, Verdict::Filtered("10-filter.wasm".to_string()));
+    assert_eq!(runs.len(), 1);
+
+    // New plugins are picked up without a restart.
+    install("30-trap.wasm", r#"(module (func (export "transform") (param i32 i32) (result i64) unreachable))"#);
+    let (verdict, runs) = plugin::run(&mut payload("Ann"));
+    let Verdict::Failed(error) = verdict else { panic!("expected the trap to fail the event") };
+    assert!(error.starts_with("Plugin 30-trap.wasm:"), "{}", error);
+    assert_eq!(runs[2].outcome, PluginOutcome::Failed);
+    assert_eq!(runs[2].error.as_deref(), Some(error.as_str()));
+
+    remove_file(format!("{}/30-trap.wasm", DIR)).unwrap();
+    install("30-spin.wasm", &format!(r#"(module {} (func (export "filter") (param i32 i32) (result i32) (loop $l (br $l)) (i32.const 1)))"#, ALLOC));
+    let (verdict, _) = plugin::run(&mut payload("Ann"));
+    assert!(matches!(&verdict, Verdict::Failed(error) if error.contains("fuel")), "{:?}", verdict);
+
+    remove_file(format!("{}/30-spin.wasm", DIR)).unwrap();
+    write(format!("{}/30-broken.wasm", DIR), b"not wasm").unwrap();
+    let broken = plugin::loaded().into_iter().find(|p| p.name == "30-broken.wasm").unwrap();
+    assert!(broken.error.is_some());
+    assert!(matches!(plugin::run(&mut payload("Ann")).0, Verdict::Failed(_)));
+    remove_file(format!("{}/30-broken.wasm", DIR)).unwrap();
+
+    // Through the handler, the runs are reported with the delivery.
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/deliveries/{id}", web::get().to(delivery_status_handler)),
+    )
+    .await;
+    let mut ids = Vec::new();
+    for (first_name, expected) in [("Ann", "ok"), ("Hi!", "dropped")] {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": "customer.created", "data": { "first_name": first_name } }))
+            .to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["status"], expected);
+        ids.push(body["delivery_id"].as_str().unwrap().to_string());
+    }
+
+    let req = actix_web::test::TestRequest::get().uri(&format!("/deliveries/{}", ids[0])).to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["plugins"][1]["plugin"], "20-rewrite.wasm");
+    assert_eq!(status["plugins"][1]["outcome"], "transformed");
+    assert!(status["plugins"][1]["elapsed_ms"].is_number());
+
+    let req = actix_web::test::TestRequest::get().uri(&format!("/deliveries/{}", ids[1])).to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "dropped");
+    assert_eq!(status["plugins"][0]["outcome"], "filtered");
+
+    // A failing plugin parks the event as it came in, for replay once fixed.
+    install("30-trap.wasm", r#"(module (func (export "transform") (param i32 i32) (result i64) unreachable))"#);
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "customer.created", "data": { "first_name": "Ann" } }))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "failed");
+    let letter = dead_letter::get(body["delivery_id"].as_str().unwrap()).expect("failed event should be kept");
+    assert!(letter.unprocessed);
+    assert_eq!(letter.payload.data, Some(serde_json::json!({ "first_name": "Ann" })));
+    remove_file(format!("{}/30-trap.wasm", DIR)).unwrap();
+}
@@ -0,0 +1,151 @@
+use actix_web::{web, App, HttpResponse, HttpServer};
+use sch2jn::handlers::{post_handler, refresh_preflight_handler};
+use sch2jn::circuit_breaker;
+use sch2jn::http_client;
+use sch2jn::preflight;
+use std::env;
+use std::fs::create_dir_all;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+static SETTINGS_CALLS: AtomicU32 = AtomicU32::new(0);
+static SETTINGS_RESHAPED: AtomicBool = AtomicBool::new(false);
+
+fn settings() -> serde_json::Value {
+    serde_json::json!({
+        "workflows": [
+            {
+                "name": "Customer",
+                "object_type": "contact",
+                "status": [
+                    { "name": "Lead" },
+                    { "name": "Job Sold" },
+                    { "name": "Archived", "is_active": false }
+                ]
+            },
+            { "name": "Roofing Job", "object_type": "job", "status": [{ "name": "Scheduled" }] },
+            { "name": "Old Customers", "object_type": "contact", "is_active": false, "status": [] }
+        ]
+    })
+}
+
+async fn mock_settings() -> HttpResponse {
+    SETTINGS_CALLS.fetch_add(1, Ordering::SeqCst);
+    if SETTINGS_RESHAPED.load(Ordering::SeqCst) {
+        return HttpResponse::Ok().json(serde_json::json!({ "account": { "workflows": settings()["workflows"] } }));
+    }
+    HttpResponse::Ok().json(settings())
+}
+
+async fn mock_contacts() -> HttpResponse {
+    HttpResponse::Created().json(serde_json::json!({ "jnid": "jn-preflight-1" }))
+}
+
+#[test]
+fn test_contact_workflows_are_read_from_settings() {
+    let workflows = preflight::parse_workflows(&settings()).unwrap();
+    assert_eq!(workflows.len(), 1);
+    assert_eq!(workflows["Customer"], vec!["Lead".to_string(), "Job Sold".to_string()]);
+
+    // Answers without contact workflows say nothing about the account.
+    assert_eq!(preflight::parse_workflows(&serde_json::json!({})), None);
+    assert_eq!(preflight::parse_workflows(&serde_json::json!({ "workflows": "Customer" })), None);
+    assert_eq!(preflight::parse_workflows(&serde_json::json!({ "workflows": [settings()["workflows"][1]] })), None);
+}
+
+#[actix_web::test]
+async fn test_contacts_jobnimbus_would_refuse_fail_fast() {
+    let _ = create_dir_all("logs");
+    create_dir_all("target/test-data/preflight_tests").unwrap();
+    env::set_var("DATA_DIR", "target/test-data/preflight_tests");
+    env::set_var("JOB_NIMBUS_API_KEY", "dummy");
+    env::set_var("JOBNIMBUS_RATE_LIMIT", "0");
+    env::set_var("CIRCUIT_FAILURE_THRESHOLD", "0");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+    env::set_var("CONTACT_PREFLIGHT", "true");
+    env::remove_var("TEST_MODE");
+
+    let mock = HttpServer::new(|| {
+        App::new()
+            .route("/api/account/settings", web::get().to(mock_settings))
+            .route("/api/contacts", web::post().to(mock_contacts))
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let addr = mock.addrs()[0];
+    actix_web::rt::spawn(mock.run());
+    env::set_var("JOBNIMBUS_BASE_URL", format!("http://{}", addr));
+    env::set_var("JOBNIMBUS_API_PREFIX", "api");
+
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/preflight/refresh", web::post().to(refresh_preflight_handler)),
+    )
+    .await;
+    let post = |data: serde_json::Value| {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": "customer.created", "data": data }))
+            .to_request();
+        actix_web::test::call_service(&app, req)
+    };
+
+    let resp = post(serde_json::json!({ "first_name": "Ann", "record_type_name": "Customer" })).await;
+    assert_eq!(resp.status(), 422);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let fields: Vec<&str> = body["fields"].as_array().unwrap().iter().map(|f| f["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["display_name", "status_name"]);
+
+    let resp = post(serde_json::json!({ "display_name": "Ann Lee", "record_type_name": "Customer", "status_name": "job sold" })).await;
+    assert_eq!(resp.status(), 422);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let error = body["fields"][0]["error"].as_str().unwrap();
+    assert!(error.contains("record type 'Customer'"), "{}", error);
+    assert!(error.contains("did you mean 'Job Sold'?"), "{}", error);
+
+    let resp = post(serde_json::json!({ "display_name": "Ann Lee", "record_type_name": "Old Customers", "status_name": "Lead" })).await;
+    assert_eq!(resp.status(), 422);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["fields"][0]["field"], "record_type_name");
+    assert!(body["fields"][0]["error"].as_str().unwrap().contains("Known: Customer"));
+
+    let resp = post(serde_json::json!({ "display_name": "Ann Lee", "record_type_name": "Customer", "status_name": "Job Sold" })).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "delivered");
+
+    // The settings were fetched once and cached for every check since.
+    assert_eq!(SETTINGS_CALLS.load(Ordering::SeqCst), 1);
+    let req = actix_web::test::TestRequest::post().uri("/preflight/refresh").to_request();
+    let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["workflows"]["Customer"][1], "Job Sold");
+    assert_eq!(SETTINGS_CALLS.load(Ordering::SeqCst), 2);
+
+    // With the circuit breaker open JobNimbus isn't asked; checks use the cached settings.
+    env::set_var("CIRCUIT_FAILURE_THRESHOLD", "1");
+    circuit_breaker::record_failure();
+    let req = actix_web::test::TestRequest::post().uri("/preflight/refresh").to_request();
+    assert_eq!(actix_web::test::call_service(&app, req).await.status(), 502);
+    let resp = post(serde_json::json!({ "display_name": "Ann Lee", "record_type_name": "Customer", "status_name": "Won" })).await;
+    assert_eq!(resp.status(), 422);
+    assert_eq!(SETTINGS_CALLS.load(Ordering::SeqCst), 2);
+    circuit_breaker::record_success();
+    env::set_var("CIRCUIT_FAILURE_THRESHOLD", "0");
+
+    // An answer in an unexpected shape doesn't replace the settings we have.
+    SETTINGS_RESHAPED.store(true, Ordering::SeqCst);
+    let req = actix_web::test::TestRequest::post().uri("/preflight/refresh").to_request();
+    assert_eq!(actix_web::test::call_service(&app, req).await.status(), 502);
+    let resp = post(serde_json::json!({ "display_name": "Ann Lee", "record_type_name": "Customer", "status_name": "Lead" })).await;
+    assert_eq!(resp.status(), 200);
+    SETTINGS_RESHAPED.store(false, Ordering::SeqCst);
+
+    // Test mode doesn't check at all.
+    env::set_var("TEST_MODE", "true");
+    let resp = post(serde_json::json!({ "first_name": "Ann" })).await;
+    assert_eq!(resp.status(), 200);
+    env::remove_var("TEST_MODE");
+
+    env::remove_var("CONTACT_PREFLIGHT");
+}
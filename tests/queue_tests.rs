@@ -0,0 +1,50 @@
+use sch2jn::handlers::Payload;
+use sch2jn::queue::{self, Delivery};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+
+fn use_test_data_dir() {
+    env::set_var("DATA_DIR", "target/test-data/queue_tests");
+    let _ = create_dir_all("logs");
+}
+
+fn sample_payload(first_name: &str) -> Payload {
+    Payload {
+        data: Some(serde_json::json!({ "first_name": first_name })),
+        _extra: HashMap::new(),
+    }
+}
+
+#[test]
+fn test_queue_round_trip() {
+    use_test_data_dir();
+    let delivery = Delivery::new(sample_payload("queued"), "{\"first_name\":\"queued\"}".to_string());
+    queue::persist(&delivery).expect("persist delivery");
+
+    let pending = queue::pending();
+    let stored = pending.iter().find(|d| d.id == delivery.id).expect("delivery should be pending");
+    assert_eq!(stored.body, delivery.body);
+    assert_eq!(stored.payload.data, delivery.payload.data);
+
+    queue::complete(&delivery.id);
+    assert!(queue::pending().iter().all(|d| d.id != delivery.id));
+}
+
+#[test]
+fn test_claim_is_exclusive() {
+    use_test_data_dir();
+    let delivery = Delivery::new(sample_payload("claimed"), "{}".to_string());
+    assert!(queue::claim(&delivery.id));
+    assert!(!queue::claim(&delivery.id), "a claimed delivery must not be handed out twice");
+    queue::release(&delivery.id);
+    assert!(queue::claim(&delivery.id));
+    queue::release(&delivery.id);
+}
+
+#[test]
+fn test_ids_sort_in_arrival_order() {
+    let first = Delivery::new(sample_payload("a"), "{}".to_string());
+    let second = Delivery::new(sample_payload("b"), "{}".to_string());
+    assert!(first.id < second.id);
+}
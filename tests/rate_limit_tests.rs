@@ -0,0 +1,56 @@
+use reqwest::{Client, Method};
+use sch2jn::delivery::{self, Outcome};
+use sch2jn::handlers::Payload;
+use sch2jn::queue::{self, Delivery};
+use sch2jn::{jobnimbus, metrics, rate_limit};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+use std::time::{Duration, Instant};
+
+// The bucket is global, so everything runs in one test to keep timings predictable.
+#[actix_web::test]
+async fn test_bucket_queues_and_defers() {
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/rate_limit_tests");
+    env::set_var("JOBNIMBUS_RATE_LIMIT", "4");
+    env::set_var("JOBNIMBUS_RATE_BURST", "2");
+
+    // The burst goes straight through, the next call waits for a refill.
+    let started = Instant::now();
+    rate_limit::acquire(Duration::from_secs(1)).await.unwrap();
+    rate_limit::acquire(Duration::from_secs(1)).await.unwrap();
+    assert!(started.elapsed() < Duration::from_millis(100));
+    assert!(rate_limit::delay() > Duration::from_millis(100));
+    rate_limit::acquire(Duration::from_secs(1)).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    let status = rate_limit::status();
+    assert!(status.enabled);
+    assert_eq!(status.burst, 2.0);
+    assert!(status.tokens < 1.0);
+    assert_eq!(status.waiting, 0);
+
+    // A delivery that would wait longer than allowed is put back untried.
+    env::set_var("JOBNIMBUS_RATE_MAX_WAIT_MS", "0");
+    let mut delivery = Delivery::new(Payload { data: Some(serde_json::json!({})), _extra: HashMap::new() }, "{}".to_string());
+    queue::claim(&delivery.id);
+    let outcome = delivery::attempt(&Client::new(), &mut delivery).await;
+    assert!(matches!(outcome, Outcome::Retrying { .. }));
+    assert_eq!(delivery.attempts, 0);
+    assert!(delivery.next_attempt_at.is_some());
+    assert!(!queue::is_claimed(&delivery.id));
+    assert_eq!(metrics::get("sch2jn_rate_limited_total"), 1);
+    assert!(metrics::render().contains("sch2jn_rate_limit_tokens"));
+    queue::complete(&delivery.id);
+
+    // A call that can't wait, like a preflight check, gets a 503 without a token.
+    let wait = rate_limit::acquire(Duration::ZERO).await.unwrap_err();
+    assert!(wait > Duration::ZERO);
+    env::set_var("JOB_NIMBUS_API_KEY", "dummy");
+    let response = jobnimbus::send(&Client::new(), Method::GET, "/account/settings", None).await.unwrap();
+    assert_eq!(response.status, 503);
+    assert!(response.limited);
+    assert!(response.retry_after.is_some_and(|after| after > Duration::ZERO));
+    assert_eq!(rate_limit::status().waiting, 0);
+}
@@ -0,0 +1,39 @@
+use sch2jn::jobnimbus::UpstreamResponse;
+use sch2jn::response::{inbound_status, Envelope};
+use std::env;
+
+#[test]
+fn test_upstream_status_map() {
+    env::remove_var("UPSTREAM_STATUS_MAP");
+    assert_eq!(inbound_status(201).as_u16(), 200);
+    assert_eq!(inbound_status(400).as_u16(), 422);
+    assert_eq!(inbound_status(401).as_u16(), 502);
+    assert_eq!(inbound_status(429).as_u16(), 503);
+    assert_eq!(inbound_status(500).as_u16(), 502);
+
+    // First match wins and unmatched statuses pass through.
+    env::set_var("UPSTREAM_STATUS_MAP", "404=200,4xx=400,bogus,5xx=503");
+    assert_eq!(inbound_status(404).as_u16(), 200);
+    assert_eq!(inbound_status(409).as_u16(), 400);
+    assert_eq!(inbound_status(504).as_u16(), 503);
+    assert_eq!(inbound_status(201).as_u16(), 201);
+    env::remove_var("UPSTREAM_STATUS_MAP");
+}
+
+#[test]
+fn test_envelope_parses_upstream_body() {
+    let json = UpstreamResponse { status: 400, body: r#"{"error":"bad email"}"#.to_string(), retry_after: None, limited: false };
+    let envelope = Envelope::new("rejected", "d-1").with_upstream(&json);
+    let value: serde_json::Value = serde_json::from_str(&envelope.to_json()).unwrap();
+    assert_eq!(value, serde_json::json!({
+        "status": "rejected",
+        "delivery_id": "d-1",
+        "upstream_status": 400,
+        "upstream_body": { "error": "bad email" }
+    }));
+
+    let text = UpstreamResponse { status: 502, body: "Bad Gateway".to_string(), retry_after: None, limited: false };
+    let envelope = Envelope::new("failed", "d-2").with_upstream(&text).with_message("gave up");
+    assert_eq!(envelope.upstream_body, Some(serde_json::json!("Bad Gateway")));
+    assert_eq!(envelope.message.as_deref(), Some("gave up"));
+}
@@ -0,0 +1,56 @@
+use sch2jn::retry::{is_retryable_status, parse_retry_after, RetryPolicy};
+use std::time::Duration;
+
+fn policy_without_jitter() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 5,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(3),
+        jitter: 0.0,
+    }
+}
+
+#[test]
+fn test_backoff_doubles_up_to_max_delay() {
+    let policy = policy_without_jitter();
+    assert_eq!(policy.next_delay(1, None), Duration::from_millis(500));
+    assert_eq!(policy.next_delay(2, None), Duration::from_millis(1000));
+    assert_eq!(policy.next_delay(3, None), Duration::from_millis(2000));
+    assert_eq!(policy.next_delay(4, None), Duration::from_secs(3));
+    assert_eq!(policy.next_delay(40, None), Duration::from_secs(3));
+}
+
+#[test]
+fn test_jitter_stays_within_bounds() {
+    let policy = RetryPolicy { jitter: 0.5, ..policy_without_jitter() };
+    for _ in 0..100 {
+        let delay = policy.next_delay(2, None);
+        assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1500));
+    }
+}
+
+#[test]
+fn test_retry_after_overrides_backoff() {
+    let policy = policy_without_jitter();
+    let retry_after = parse_retry_after("120");
+    assert_eq!(retry_after, Some(Duration::from_secs(120)));
+    assert_eq!(policy.next_delay(1, parse_retry_after("2")), Duration::from_secs(2));
+
+    // It can't push the next attempt past our own longest delay.
+    assert_eq!(policy.next_delay(1, retry_after), Duration::from_secs(3));
+    assert_eq!(policy.next_delay(1, parse_retry_after("18446744073709551615")), Duration::from_secs(3));
+
+    // Dates in the past mean "now".
+    assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+    assert_eq!(parse_retry_after("soon"), None);
+}
+
+#[test]
+fn test_retryable_statuses() {
+    assert!(is_retryable_status(429));
+    assert!(is_retryable_status(500));
+    assert!(is_retryable_status(503));
+    assert!(!is_retryable_status(200));
+    assert!(!is_retryable_status(400));
+    assert!(!is_retryable_status(401));
+}
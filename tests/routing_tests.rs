@@ -0,0 +1,164 @@
+use actix_web::{web, App, HttpResponse, HttpServer};
+use chrono::Local;
+use sch2jn::handlers::{post_handler, Payload};
+use sch2jn::queue::Delivery;
+use sch2jn::routing::{glob_match, ignore_reason, route, shape, Target};
+use sch2jn::{http_client, idmap, jobnimbus, metrics};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+use std::sync::Mutex;
+
+// Tests that set environment variables take this so they don't see each
+// other's settings.
+static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+static JOB_CALLS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+async fn mock_create_job() -> HttpResponse {
+    JOB_CALLS.lock().unwrap().push("POST".to_string());
+    HttpResponse::Created().json(serde_json::json!({ "jnid": "jn-job-1" }))
+}
+
+async fn mock_update_job(jnid: web::Path<String>) -> HttpResponse {
+    JOB_CALLS.lock().unwrap().push(format!("PUT {}", jnid));
+    HttpResponse::Ok().json(serde_json::json!({ "jnid": jnid.into_inner() }))
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("project.*", "project.updated"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("job.?tage*", "job.stage_changed"));
+    assert!(glob_match("*.updated", "contact.address.updated"));
+    assert!(!glob_match("project.*", "contact.updated"));
+    assert!(!glob_match("project", "project.updated"));
+}
+
+#[test]
+fn test_routes_are_checked_in_order() {
+    let _env = ENV_LOCK.blocking_lock();
+    env::set_var("EVENT_ROUTES", "project.created=contacts, project.*=jobs, note.*=activity, bad-route");
+    assert_eq!(route(Some("project.created")), Target::Contacts);
+    assert_eq!(route(Some("project.updated")), Target::Jobs);
+    assert_eq!(route(Some("note.added")), Target::Activities);
+    assert_eq!(route(Some("customer.updated")), Target::Contacts);
+    assert_eq!(route(None), Target::Contacts);
+    env::remove_var("EVENT_ROUTES");
+}
+
+#[test]
+fn test_activities_are_shaped_and_linked_to_contact() {
+    let _env = ENV_LOCK.blocking_lock();
+    env::set_var("DATA_DIR", "target/test-data/routing_tests");
+    idmap::record("customer-9", "jn-contact-9", None);
+    let payload = Payload {
+        data: Some(serde_json::json!({ "customer_id": "customer-9", "description": "Roof inspected" })),
+        _extra: HashMap::new(),
+    };
+
+    let shaped = shape(Target::Activities, payload.data.clone().unwrap(), &payload);
+    assert_eq!(shaped["note"], "Roof inspected");
+    assert_eq!(shaped["record_type_name"], "Note");
+    assert_eq!(shaped["primary"]["id"], "jn-contact-9");
+
+    // Contacts are passed through untouched.
+    let contact = shape(Target::Contacts, payload.data.clone().unwrap(), &payload);
+    assert_eq!(contact, payload.data.unwrap());
+}
+
+#[actix_web::test]
+async fn test_filtered_events_are_ignored() {
+    let _env = ENV_LOCK.lock().await;
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/routing_tests");
+    env::set_var("TEST_MODE", "true");
+    env::set_var("EVENT_ALLOWLIST", "customer.*, project.*");
+    env::set_var("EVENT_DENYLIST", "*.viewed");
+
+    assert_eq!(ignore_reason(Some("customer.created")), None);
+    assert!(ignore_reason(Some("customer.viewed")).unwrap().contains("deny list"));
+    assert!(ignore_reason(Some("note.added")).unwrap().contains("allow list"));
+    assert!(ignore_reason(None).is_some());
+
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler)),
+    )
+    .await;
+    let before = metrics::get("sch2jn_events_ignored_total");
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "project.viewed", "data": { "id": "p-1" } }))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ignored");
+    assert!(body.get("delivery_id").is_none());
+    assert!(body["message"].as_str().unwrap().contains("'project.viewed'"));
+    assert_eq!(metrics::get("sch2jn_events_ignored_total"), before + 1);
+
+    // Denied events are acknowledged before the payload itself is checked.
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "customer.viewed" }))
+        .to_request();
+    let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["status"], "ignored");
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "customer.created" }))
+        .to_request();
+    assert_eq!(actix_web::test::call_service(&app, req).await.status(), 400);
+
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "project.updated", "data": { "id": "p-1" } }))
+        .to_request();
+    let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["status"], "ok");
+
+    env::remove_var("EVENT_ALLOWLIST");
+    env::remove_var("EVENT_DENYLIST");
+}
+
+#[actix_web::test]
+async fn test_later_events_update_the_same_job() {
+    let _env = ENV_LOCK.lock().await;
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/routing_tests");
+    env::set_var("JOB_NIMBUS_API_KEY", "dummy");
+    env::set_var("JOBNIMBUS_RATE_LIMIT", "0");
+
+    let mock = HttpServer::new(|| {
+        App::new()
+            .route("/api/jobs", web::post().to(mock_create_job))
+            .route("/api/jobs/{jnid}", web::put().to(mock_update_job))
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let addr = mock.addrs()[0];
+    actix_web::rt::spawn(mock.run());
+    env::set_var("JOBNIMBUS_BASE_URL", format!("http://{}", addr));
+    env::set_var("JOBNIMBUS_API_PREFIX", "api");
+
+    let project = format!("project-{}", Local::now().timestamp_nanos_opt().unwrap());
+    let client = http_client::build().unwrap();
+    for stage in ["Scheduled", "Completed"] {
+        let data = serde_json::json!({ "id": project, "status_name": stage });
+        let mut extra = HashMap::new();
+        extra.insert("event".to_string(), serde_json::json!("job.stage_changed"));
+        let mut delivery = Delivery::new(Payload { data: Some(data.clone()), _extra: extra }, data.to_string());
+        delivery.target = Target::Jobs;
+        assert!(jobnimbus::forward(&client, &delivery).await.unwrap().is_success());
+    }
+    env::remove_var("JOBNIMBUS_BASE_URL");
+    env::remove_var("JOBNIMBUS_API_PREFIX");
+
+    assert_eq!(*JOB_CALLS.lock().unwrap(), vec!["POST".to_string(), "PUT jn-job-1".to_string()]);
+    // The job is mapped under the plain SCH id, apart from any customer with the same id.
+    let mapping = idmap::by_sch_id_for(Target::Jobs, &project).unwrap();
+    assert_eq!(mapping.sch_id, project);
+    assert_eq!(mapping.target, Target::Jobs);
+    assert_eq!(mapping.jnid, "jn-job-1");
+    assert_eq!(idmap::lookup_jnid(&project), None);
+}
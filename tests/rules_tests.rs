@@ -0,0 +1,199 @@
+use actix_web::{web, App};
+use sch2jn::dead_letter;
+use sch2jn::handlers::{delivery_status_handler, post_handler, Payload};
+use sch2jn::http_client;
+use sch2jn::routing::Target;
+use sch2jn::rules::{self, Action, Condition, RulesConfig};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{create_dir_all, rename, write};
+use std::sync::Once;
+
+static SETUP: Once = Once::new();
+
+fn setup() {
+    SETUP.call_once(|| {
+        let _ = create_dir_all("logs");
+        create_dir_all("target/test-data/rules_tests").unwrap();
+        write(
+            "target/test-data/rules_tests/rules.json",
+            serde_json::json!({
+                "rules": [
+                    { "name": "no-tests", "when": { "field": "email", "matches": "@example\\.test$" }, "action": "drop" },
+                    { "name": "big", "when": { "event": "project.*", "field": "amount", "gte": 50000 }, "action": "hold" },
+                    {
+                        "name": "insurance",
+                        "when": { "all": [{ "event": "project.*" }, { "field": "claim", "present": true }] },
+                        "action": "route",
+                        "target": "jobs",
+                        "mapping": "insurance_job"
+                    },
+                    { "when": { "field": "source", "equals": "referral", "not": { "field": "vip", "equals": true } }, "action": "tag", "tags": ["referral"] },
+                    { "name": "promos", "when": { "event": "promo.*" }, "action": "route", "mapping": "promo_contact" }
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        write_mappings(&["insurance_job", "promo_contact"]);
+        env::set_var("RULES_FILE", "target/test-data/rules_tests/rules.json");
+        env::set_var("MAPPINGS_FILE", "target/test-data/rules_tests/mappings.json");
+        env::set_var("DATA_DIR", "target/test-data/rules_tests");
+        env::set_var("TEST_MODE", "true");
+        env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+        // Load the rules while every mapping they name is there.
+        assert_eq!(rules::current().rules.len(), 5);
+    });
+}
+
+fn write_mappings(names: &[&str]) {
+    let mappings: Vec<serde_json::Value> = names
+        .iter()
+        .map(|name| match *name {
+            "insurance_job" => serde_json::json!({ "name": name, "fields": { "name": "title", "claim_number": "claim" } }),
+            _ => serde_json::json!({ "name": name, "fields": { "display_name": "name" } }),
+        })
+        .collect();
+    // Replaced in one step so tests running alongside never read half a file.
+    let staged = "target/test-data/rules_tests/mappings.json.new";
+    write(staged, serde_json::json!({ "mappings": mappings }).to_string()).unwrap();
+    rename(staged, "target/test-data/rules_tests/mappings.json").unwrap();
+}
+
+fn payload(event: &str, data: serde_json::Value) -> Payload {
+    let mut extra = HashMap::new();
+    extra.insert("event".to_string(), serde_json::json!(event));
+    Payload { data: Some(data), _extra: extra }
+}
+
+#[test]
+fn test_patterns_are_compiled_when_rules_load() {
+    let error = serde_json::from_value::<Condition>(serde_json::json!({ "field": "email", "matches": "(" })).unwrap_err();
+    assert!(error.to_string().contains("invalid regex '('"), "{}", error);
+
+    let condition: Condition = serde_json::from_value(serde_json::json!({ "field": "email", "matches": "@example\\.test$" })).unwrap();
+    assert_eq!(serde_json::to_value(&condition).unwrap()["matches"], "@example\\.test$");
+}
+
+#[test]
+fn test_rules_are_checked_when_they_load() {
+    setup();
+    let config = |rule: serde_json::Value| serde_json::from_value::<RulesConfig>(serde_json::json!({ "rules": [rule] })).unwrap();
+
+    assert!(rules::validate(&config(serde_json::json!({ "action": "route", "mapping": "insurance_job" }))).is_ok());
+    assert!(rules::validate(&config(serde_json::json!({ "action": "route", "target": "jobs" }))).is_ok());
+
+    let error = rules::validate(&config(serde_json::json!({ "name": "typo", "action": "tag", "mapping": "insurance" }))).unwrap_err();
+    assert_eq!(error, "rule 'typo': no mapping named 'insurance'");
+    let error = rules::validate(&config(serde_json::json!({ "action": "route" }))).unwrap_err();
+    assert_eq!(error, "rule '#1': route needs a target or a mapping");
+}
+
+#[test]
+fn test_first_matching_rule_decides() {
+    setup();
+
+    let decision = rules::evaluate(&payload("customer.created", serde_json::json!({ "email": "qa@example.test" })));
+    assert_eq!(decision.action, Action::Drop);
+    assert_eq!(decision.rule.as_deref(), Some("no-tests"));
+
+    // Matches both the hold and the insurance rule; the earlier one wins.
+    let decision = rules::evaluate(&payload("project.created", serde_json::json!({ "amount": "75000", "claim": "C-1" })));
+    assert_eq!(decision.action, Action::Hold);
+
+    let decision = rules::evaluate(&payload("project.created", serde_json::json!({ "amount": 1200, "claim": "C-1" })));
+    assert_eq!(decision.action, Action::Route);
+    assert_eq!(decision.target, Some(Target::Jobs));
+    assert_eq!(decision.mapping.as_deref(), Some("insurance_job"));
+
+    let decision = rules::evaluate(&payload("customer.created", serde_json::json!({ "source": "referral" })));
+    assert_eq!(decision.action, Action::Tag);
+    assert_eq!(decision.rule.as_deref(), Some("#4"));
+    assert_eq!(decision.tags, vec!["referral".to_string()]);
+
+    let decision = rules::evaluate(&payload("customer.created", serde_json::json!({ "source": "referral", "vip": true })));
+    assert_eq!(decision.rule, None);
+    assert_eq!(decision.action, Action::Route);
+}
+
+#[actix_web::test]
+async fn test_rule_actions_on_inbound_payloads() {
+    setup();
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/deliveries/{id}", web::get().to(delivery_status_handler)),
+    )
+    .await;
+    let post = |event: &str, data: serde_json::Value| {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": event, "data": data }))
+            .to_request();
+        actix_web::test::call_service(&app, req)
+    };
+    let status_of = |id: &str| {
+        let req = actix_web::test::TestRequest::get().uri(&format!("/deliveries/{}", id)).to_request();
+        actix_web::test::call_and_read_body_json::<_, _, serde_json::Value>(&app, req)
+    };
+
+    let resp = post("customer.created", serde_json::json!({ "email": "qa@example.test" })).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "dropped");
+    let status = status_of(body["delivery_id"].as_str().unwrap()).await;
+    assert_eq!(status["status"], "dropped");
+    assert_eq!(status["rule"], "no-tests");
+
+    let resp = post("project.created", serde_json::json!({ "amount": 90000 })).await;
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "held");
+    let id = body["delivery_id"].as_str().unwrap();
+    assert!(dead_letter::get(id).unwrap().reason.contains("big"));
+    assert_eq!(status_of(id).await["status"], "held");
+    dead_letter::discard(id);
+
+    let resp = post("project.created", serde_json::json!({ "title": "Hail damage", "claim": "C-9" })).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let status = status_of(body["delivery_id"].as_str().unwrap()).await;
+    assert_eq!(status["status"], "succeeded");
+    assert_eq!(status["target"], "jobs");
+    assert_eq!(status["rule"], "insurance");
+
+    let resp = post("customer.created", serde_json::json!({ "source": "referral" })).await;
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let status = status_of(body["delivery_id"].as_str().unwrap()).await;
+    assert_eq!(status["tags"], serde_json::json!(["referral"]));
+}
+
+#[actix_web::test]
+async fn test_mappings_removed_after_the_rules_loaded_park_the_event() {
+    setup();
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler)),
+    )
+    .await;
+    let post = || {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": "promo.signup", "data": { "name": "Ann" } }))
+            .to_request();
+        actix_web::test::call_service(&app, req)
+    };
+
+    assert_eq!(post().await.status(), 200);
+
+    write_mappings(&["insurance_job"]);
+    let resp = post().await;
+    write_mappings(&["insurance_job", "promo_contact"]);
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "failed");
+    let id = body["delivery_id"].as_str().unwrap();
+    let letter = dead_letter::get(id).expect("event should be parked");
+    assert_eq!(letter.reason, "Rule 'promos' names unknown mapping 'promo_contact'");
+    dead_letter::discard(id);
+}
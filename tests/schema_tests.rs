@@ -0,0 +1,102 @@
+use actix_web::{web, App};
+use sch2jn::handlers::post_handler;
+use sch2jn::http_client;
+use sch2jn::metrics;
+use sch2jn::schema::{self, SchemaMode};
+use std::env;
+use std::fs::{create_dir_all, remove_dir_all, write};
+
+const DIR: &str = "target/test-data/schema_tests/schemas";
+
+fn install(name: &str, schema: serde_json::Value) {
+    write(format!("{}/{}", DIR, name), schema.to_string()).unwrap();
+}
+
+#[actix_web::test]
+async fn test_payloads_are_checked_against_their_schema() {
+    let _ = create_dir_all("logs");
+    let _ = remove_dir_all(DIR);
+    create_dir_all(DIR).unwrap();
+    env::set_var("SCHEMAS_DIR", DIR);
+    env::set_var("DATA_DIR", "target/test-data/schema_tests");
+    env::set_var("TEST_MODE", "true");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+    env::remove_var("SCHEMA_MODE");
+
+    install("customer.created.json", serde_json::json!({
+        "type": "object",
+        "required": ["email"],
+        "properties": {
+            "email": { "type": "string" },
+            "phones": { "type": "array", "items": { "type": "object", "required": ["number"] } }
+        }
+    }));
+    install("project.*.json", serde_json::json!({
+        "x-schema-mode": "warn",
+        "type": "object",
+        "required": ["title"]
+    }));
+    install("task.*.json", serde_json::json!({ "x-schema-mode": "sometimes" }));
+
+    let schemas = schema::loaded();
+    assert_eq!(schemas.len(), 3);
+    assert_eq!(schemas[1].event, "project.*");
+    assert_eq!(schemas[1].mode, Some(SchemaMode::Warn));
+    assert!(schemas[2].error.is_some());
+
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler)),
+    )
+    .await;
+    let post = |event: &str, data: serde_json::Value| {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": event, "data": data }))
+            .to_request();
+        actix_web::test::call_service(&app, req)
+    };
+
+    let resp = post("customer.created", serde_json::json!({ "email": "ann@example.com" })).await;
+    assert_eq!(resp.status(), 200);
+
+    let resp = post("customer.created", serde_json::json!({ "email": 7, "phones": [{ "type": "mobile" }] })).await;
+    assert_eq!(resp.status(), 422);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["schema"], "customer.created.json");
+    let pointers: Vec<&str> = body["errors"].as_array().unwrap().iter().map(|e| e["pointer"].as_str().unwrap()).collect();
+    assert!(pointers.contains(&"/email"), "{:?}", pointers);
+    assert!(pointers.contains(&"/phones/0"), "{:?}", pointers);
+
+    // Warn mode forwards the payload and only counts the violation.
+    let warnings = metrics::get("sch2jn_schema_warnings_total");
+    let resp = post("project.updated", serde_json::json!({ "name": "Roof" })).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(metrics::get("sch2jn_schema_warnings_total"), warnings + 1);
+
+    // So does SCHEMA_MODE for schemas that don't choose.
+    env::set_var("SCHEMA_MODE", "warn");
+    let resp = post("customer.created", serde_json::json!({})).await;
+    assert_eq!(resp.status(), 200);
+    env::remove_var("SCHEMA_MODE");
+
+    // Events without a schema pass.
+    assert_eq!(post("note.created", serde_json::json!({})).await.status(), 200);
+
+    // A schema that failed to load doesn't wave payloads through when enforced.
+    let errors = metrics::get("sch2jn_schema_errors_total");
+    let resp = post("task.created", serde_json::json!({})).await;
+    assert_eq!(resp.status(), 503);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["schema"], "task.*.json");
+    assert_eq!(metrics::get("sch2jn_schema_errors_total"), errors + 1);
+
+    // Nor does a broken exact match fall back to a glob.
+    write(format!("{}/project.closed.json", DIR), "{ not json").unwrap();
+    assert_eq!(post("project.closed", serde_json::json!({ "name": "Roof" })).await.status(), 503);
+
+    env::set_var("SCHEMA_MODE", "warn");
+    assert_eq!(post("task.created", serde_json::json!({})).await.status(), 200);
+    assert_eq!(metrics::get("sch2jn_schema_errors_total"), errors + 3);
+    env::remove_var("SCHEMA_MODE");
+}
@@ -0,0 +1,175 @@
+use actix_web::{web, App};
+use sch2jn::handlers::{delivery_status_handler, post_handler, replay_dead_letter_handler, Payload};
+use sch2jn::{dead_letter, http_client};
+use sch2jn::script::{self, ScriptOutcome};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{create_dir_all, remove_file, write};
+use std::sync::Once;
+
+static SETUP: Once = Once::new();
+
+const TERRITORY: &str = r#"
+if data.status == "test" {
+    return drop("test record");
+}
+let state = data.address.state;
+out.record_type_name = if state == "FL" || state == "GA" { "Southeast Customer" } else { "Customer" };
+out.display_name = `${data.first} ${data.last}`;
+"#;
+
+fn setup() {
+    SETUP.call_once(|| {
+        let _ = create_dir_all("logs");
+        let dir = "target/test-data/script_tests/scripts";
+        create_dir_all(dir).unwrap();
+        write(format!("{}/territory.rhai", dir), TERRITORY).unwrap();
+        write(format!("{}/broken.rhai", dir), "let total = data.amount;\nlet x = total / missing_var;\nout").unwrap();
+        write(format!("{}/spin.rhai", dir), "loop { }").unwrap();
+        write(format!("{}/number.rhai", dir), "42").unwrap();
+        write(format!("{}/helper.rhai", dir), "fn region() { \"FL\" }").unwrap();
+        write(format!("{}/importer.rhai", dir), "import \"helper\" as h;\nout.region = h::region();\nout").unwrap();
+        write(format!("{}/flaky.rhai", dir), "out.total = data.amount / missing_var;\nout").unwrap();
+        write(
+            "target/test-data/script_tests/mappings.json",
+            serde_json::json!({
+                "mappings": [
+                    { "event": "customer.*", "fields": { "email": "email" }, "script": "territory.rhai" },
+                    { "event": "broken.*", "script": "broken.rhai" },
+                    { "event": "flaky.*", "script": "flaky.rhai" }
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        create_dir_all("target/test-data/script_tests/schemas").unwrap();
+        env::set_var("SCHEMAS_DIR", "target/test-data/script_tests/schemas");
+        env::set_var("SCRIPTS_DIR", dir);
+        env::set_var("MAPPINGS_FILE", "target/test-data/script_tests/mappings.json");
+        env::set_var("DATA_DIR", "target/test-data/script_tests");
+        env::set_var("SCRIPT_MAX_OPERATIONS", "10000");
+        env::set_var("TEST_MODE", "true");
+        env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+    });
+}
+
+fn payload(data: serde_json::Value) -> Payload {
+    let mut extra = HashMap::new();
+    extra.insert("event".to_string(), serde_json::json!("customer.created"));
+    Payload { data: Some(data), _extra: extra }
+}
+
+#[test]
+fn test_scripts_shape_drop_and_fail() {
+    setup();
+    let customer = payload(serde_json::json!({ "first": "Ann", "last": "Lee", "address": { "state": "FL" } }));
+    let outcome = script::run("territory.rhai", &customer, serde_json::json!({ "email": "ann@example.com" })).unwrap();
+    assert_eq!(outcome, ScriptOutcome::Send(serde_json::json!({
+        "email": "ann@example.com",
+        "record_type_name": "Southeast Customer",
+        "display_name": "Ann Lee"
+    })));
+
+    let test_record = payload(serde_json::json!({ "status": "test" }));
+    assert_eq!(
+        script::run("territory.rhai", &test_record, serde_json::json!({})).unwrap(),
+        ScriptOutcome::Drop("test record".to_string())
+    );
+
+    let error = script::run("broken.rhai", &customer, serde_json::json!({})).unwrap_err();
+    assert!(error.starts_with("Script broken.rhai:"), "{}", error);
+    assert!(error.contains("line 2"), "{}", error);
+
+    let error = script::run("spin.rhai", &customer, serde_json::json!({})).unwrap_err();
+    assert!(error.contains("Too many operations"), "{}", error);
+
+    let error = script::run("number.rhai", &customer, serde_json::json!({})).unwrap_err();
+    assert!(error.contains("expected an object map"), "{}", error);
+
+    assert!(script::run("../escape.rhai", &customer, serde_json::json!({})).is_err());
+
+    // Modules can't be loaded, not even from SCRIPTS_DIR.
+    let error = script::run("importer.rhai", &customer, serde_json::json!({})).unwrap_err();
+    assert!(error.contains("helper"), "{}", error);
+}
+
+#[actix_web::test]
+async fn test_script_results_reach_the_sender() {
+    setup();
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/deliveries/{id}", web::get().to(delivery_status_handler)),
+    )
+    .await;
+    let post = |event: &str, data: serde_json::Value| {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": event, "data": data }))
+            .to_request();
+        actix_web::test::call_service(&app, req)
+    };
+
+    let resp = post("customer.created", serde_json::json!({ "status": "test" })).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "dropped");
+    assert!(body["message"].as_str().unwrap().contains("test record"));
+
+    let resp = post("broken.thing", serde_json::json!({ "amount": 5 })).await;
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "failed");
+    assert!(body["message"].as_str().unwrap().contains("line 2"));
+    let id = body["delivery_id"].as_str().unwrap();
+    let letter = dead_letter::get(id).expect("failed event should be kept");
+    assert!(letter.unprocessed);
+    assert!(letter.reason.contains("line 2"));
+
+    let req = actix_web::test::TestRequest::get()
+        .uri(&format!("/deliveries/{}", id))
+        .to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "failed");
+    assert!(status["error"].as_str().unwrap().contains("line 2"));
+}
+
+#[actix_web::test]
+async fn test_failed_script_events_replay_once_fixed() {
+    setup();
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/dead_letters/{id}/replay", web::post().to(replay_dead_letter_handler)),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "event": "flaky.thing", "data": { "amount": 5 } }))
+        .to_request();
+    let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["status"], "failed");
+    let id = body["delivery_id"].as_str().unwrap().to_string();
+
+    write("target/test-data/script_tests/scripts/flaky.rhai", "out.total = data.amount * 2;\nout").unwrap();
+    let replay = || {
+        let req = actix_web::test::TestRequest::post()
+            .uri(&format!("/dead_letters/{}/replay", id))
+            .to_request();
+        actix_web::test::call_service(&app, req)
+    };
+
+    // A rerun that is refused on the way leaves the letter where it was.
+    let schema = "target/test-data/script_tests/schemas/flaky.thing.json";
+    write(schema, serde_json::json!({ "type": "object", "required": ["currency"] }).to_string()).unwrap();
+    assert_eq!(replay().await.status(), 422);
+    assert!(dead_letter::get(&id).unwrap().unprocessed);
+
+    remove_file(schema).unwrap();
+    let resp = replay().await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ok");
+    assert!(dead_letter::get(&id).is_none());
+}
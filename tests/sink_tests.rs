@@ -0,0 +1,180 @@
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use sch2jn::delivery::{self, Outcome};
+use sch2jn::handlers::Payload;
+use sch2jn::http_client;
+use sch2jn::queue::{self, Delivery};
+use sch2jn::sink::{SinkConfig, SinkState};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, remove_file, write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+static CONTACT_CALLS: AtomicU32 = AtomicU32::new(0);
+static HOOK_CALLS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+async fn mock_contacts() -> HttpResponse {
+    // Down on the first call, fine afterwards.
+    if CONTACT_CALLS.fetch_add(1, Ordering::SeqCst) == 0 {
+        return HttpResponse::ServiceUnavailable().finish();
+    }
+    HttpResponse::Created().json(serde_json::json!({ "jnid": "jn-sink-1" }))
+}
+
+async fn mock_hook(req: HttpRequest) -> HttpResponse {
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+    HOOK_CALLS.lock().unwrap().push((header("authorization"), header("x-delivery-id")));
+    HttpResponse::Ok().finish()
+}
+
+#[test]
+fn test_invalid_sinks_are_rejected() {
+    let bad_url: SinkConfig = serde_json::from_value(serde_json::json!({ "type": "webhook", "url": "not a url" })).unwrap();
+    assert!(bad_url.build().is_err());
+    let bad_method: SinkConfig =
+        serde_json::from_value(serde_json::json!({ "type": "webhook", "url": "https://example.com", "method": "NOT A METHOD" })).unwrap();
+    assert!(bad_method.build().is_err());
+    let file: SinkConfig = serde_json::from_value(serde_json::json!({ "type": "file", "path": "events.ndjson" })).unwrap();
+    assert_eq!(file.build().unwrap().name(), "file");
+    let no_plugin: SinkConfig = serde_json::from_value(serde_json::json!({ "type": "plugin", "plugin": " " })).unwrap();
+    assert!(no_plugin.build().is_err());
+}
+
+/// Accepts deliveries with `202`, or rejects them with `422` when they contain a `!`.
+const DELIVER_PLUGIN: &str = r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "deliver") (param $ptr i32) (param $len i32) (result i32)
+    (local $end i32)
+    (local.set $end (i32.add (local.get $ptr) (local.get $len)))
+    (block $done
+      (loop $scan
+        (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
+        (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 33))
+          (then (return (i32.const 422))))
+        (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+        (br $scan)))
+    (i32.const 202)))"#;
+
+#[actix_web::test]
+async fn test_plugin_sink_answers_with_a_status() {
+    let _ = create_dir_all("logs");
+    let dir = "target/test-data/sink_tests/plugins";
+    let _ = remove_dir_all(dir);
+    create_dir_all(dir).unwrap();
+    env::set_var("PLUGINS_DIR", dir);
+    write(format!("{}/deliver.wasm", dir), wat::parse_str(DELIVER_PLUGIN).unwrap()).unwrap();
+    write(format!("{}/trap.wasm", dir), wat::parse_str(r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (i32.const 0))
+  (func (export "deliver") (param i32 i32) (result i32) unreachable))"#).unwrap()).unwrap();
+
+    let sink = |plugin: &str| {
+        let config: SinkConfig = serde_json::from_value(serde_json::json!({ "type": "plugin", "plugin": plugin })).unwrap();
+        config.build().unwrap()
+    };
+    let delivery = |first_name: &str| {
+        let payload = Payload { data: Some(serde_json::json!({ "first_name": first_name })), _extra: HashMap::new() };
+        Delivery::new(payload, serde_json::json!({ "first_name": first_name }).to_string())
+    };
+    let client = http_client::build().unwrap();
+
+    assert_eq!(sink("deliver.wasm").name(), "plugin");
+    assert_eq!(sink("deliver.wasm").send(&client, &delivery("Ann")).await.unwrap().status, 202);
+    assert_eq!(sink("deliver.wasm").send(&client, &delivery("Hi!")).await.unwrap().status, 422);
+
+    // Without an answer the delivery is retried.
+    let error = sink("trap.wasm").send(&client, &delivery("Ann")).await.unwrap_err();
+    assert!(error.starts_with("Plugin trap.wasm:"), "{}", error);
+    assert!(sink("missing.wasm").send(&client, &delivery("Ann")).await.is_err());
+}
+
+#[actix_web::test]
+async fn test_delivery_fans_out_to_every_sink() {
+    let _ = create_dir_all("logs");
+    create_dir_all("target/test-data/sink_tests").unwrap();
+    let ndjson = "target/test-data/sink_tests/events.ndjson";
+    let _ = remove_file(ndjson);
+    env::set_var("DATA_DIR", "target/test-data/sink_tests");
+    env::set_var("JOB_NIMBUS_API_KEY", "dummy");
+    env::set_var("JOBNIMBUS_RATE_LIMIT", "0");
+    env::set_var("CIRCUIT_FAILURE_THRESHOLD", "0");
+    env::set_var("RETRY_MAX_ATTEMPTS", "5");
+    env::set_var("SINK_TEST_TOKEN", "s3cret");
+    env::remove_var("TEST_MODE");
+
+    let mock = HttpServer::new(|| {
+        App::new()
+            .route("/api/contacts", web::post().to(mock_contacts))
+            .route("/hook", web::put().to(mock_hook))
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let addr = mock.addrs()[0];
+    actix_web::rt::spawn(mock.run());
+    env::set_var("JOBNIMBUS_BASE_URL", format!("http://{}", addr));
+    env::set_var("JOBNIMBUS_API_PREFIX", "api");
+
+    write(
+        "target/test-data/sink_tests/sinks.json",
+        serde_json::json!({
+            "sinks": [
+                { "type": "jobnimbus" },
+                {
+                    "type": "webhook",
+                    "name": "crm",
+                    "url": format!("http://{}/hook", addr),
+                    "method": "put",
+                    "headers": { "Authorization": "Bearer ${SINK_TEST_TOKEN}" }
+                },
+                { "type": "file", "name": "archive", "path": ndjson }
+            ]
+        })
+        .to_string(),
+    )
+    .unwrap();
+    env::set_var("SINKS_FILE", "target/test-data/sink_tests/sinks.json");
+
+    let mut extra = HashMap::new();
+    extra.insert("event".to_string(), serde_json::json!("customer.created"));
+    let payload = Payload { data: Some(serde_json::json!({ "first_name": "Fan" })), _extra: extra };
+    let mut delivery = Delivery::new(payload, serde_json::json!({ "first_name": "Fan" }).to_string());
+    queue::persist(&delivery).unwrap();
+    let client = http_client::build().unwrap();
+
+    // JobNimbus is down; the other sinks get the delivery anyway.
+    queue::claim(&delivery.id);
+    assert!(matches!(delivery::attempt(&client, &mut delivery).await, Outcome::Retrying { .. }));
+    assert_eq!(delivery.sinks["jobnimbus"].status, SinkState::Pending);
+    assert_eq!(delivery.sinks["jobnimbus"].upstream_status, Some(503));
+    assert_eq!(delivery.sinks["crm"].status, SinkState::Succeeded);
+    assert_eq!(delivery.sinks["archive"].status, SinkState::Succeeded);
+
+    // The retry only goes to JobNimbus.
+    delivery.next_attempt_at = None;
+    queue::claim(&delivery.id);
+    match delivery::attempt(&client, &mut delivery).await {
+        Outcome::Delivered(response) => assert_eq!(response.jnid().as_deref(), Some("jn-sink-1")),
+        _ => panic!("expected the retry to be delivered"),
+    }
+    assert_eq!(delivery.sinks["jobnimbus"].status, SinkState::Succeeded);
+    assert_eq!(delivery.sinks["jobnimbus"].attempts, 2);
+    assert_eq!(delivery.sinks["crm"].attempts, 1);
+    assert!(queue::get(&delivery.id).is_none());
+
+    let hooks = HOOK_CALLS.lock().unwrap().clone();
+    assert_eq!(hooks, vec![("Bearer s3cret".to_string(), delivery.id.clone())]);
+
+    let lines: Vec<serde_json::Value> = read_to_string(ndjson)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["delivery_id"], delivery.id.as_str());
+    assert_eq!(lines[0]["event"], "customer.created");
+    assert_eq!(lines[0]["body"]["first_name"], "Fan");
+
+    env::remove_var("SINKS_FILE");
+}
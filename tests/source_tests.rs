@@ -0,0 +1,134 @@
+use actix_web::{web, App};
+use sch2jn::handlers::{delivery_status_handler, source_handler};
+use sch2jn::{http_client, queue};
+use sch2jn::source::{self, SourceConfig, SourceFormat};
+use std::env;
+use std::fs::{create_dir_all, write};
+
+fn config(format: SourceFormat) -> SourceConfig {
+    SourceConfig { format, data_path: None, event_path: None, event: None }
+}
+
+#[test]
+fn test_formats_are_normalized() {
+    let sch = source::normalize("sch", &config(SourceFormat::Sch), br#"{"event":"customer.created","data":{"id":"c-1"}}"#).unwrap();
+    assert_eq!(sch.len(), 1);
+    assert_eq!(sch[0].data, Some(serde_json::json!({ "id": "c-1" })));
+    assert_eq!(sch[0]._extra["event"], "customer.created");
+    assert_eq!(sch[0]._extra["source"], "sch");
+
+    let json = SourceConfig {
+        data_path: Some("payload.customer".to_string()),
+        event_path: Some("type".to_string()),
+        ..config(SourceFormat::Json)
+    };
+    let events = source::normalize("acme", &json, br#"{"type":"lead.new","payload":{"customer":{"name":"Ann"}}}"#).unwrap();
+    assert_eq!(events[0].data, Some(serde_json::json!({ "name": "Ann" })));
+    assert_eq!(events[0]._extra["event"], "lead.new");
+    assert_eq!(events[0]._extra["source"], "acme");
+    assert!(source::normalize("acme", &json, b"[]").is_err());
+
+    let form = SourceConfig { event: Some("form.submitted".to_string()), ..config(SourceFormat::Form) };
+    let events = source::normalize("site", &form, b"name=Jo+Smith&email=jo%40example.com&interest=roof&interest=gutters").unwrap();
+    assert_eq!(events[0].data, Some(serde_json::json!({
+        "name": "Jo Smith",
+        "email": "jo@example.com",
+        "interest": ["roof", "gutters"]
+    })));
+    assert_eq!(events[0]._extra["event"], "form.submitted");
+
+    let batch = source::normalize("bulk", &config(SourceFormat::Batch), br#"[{"event":"a","data":{}},{"event":"b","data":{}}]"#).unwrap();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch[1]._extra["event"], "b");
+    assert!(source::normalize("bulk", &config(SourceFormat::Batch), br#"[{"event":"a","data":{}}, 3]"#).is_err());
+}
+
+#[actix_web::test]
+async fn test_source_endpoints_feed_the_pipeline() {
+    let _ = create_dir_all("logs");
+    create_dir_all("target/test-data/source_tests").unwrap();
+    env::set_var("DATA_DIR", "target/test-data/source_tests");
+    env::set_var("TEST_MODE", "true");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+    write(
+        "target/test-data/source_tests/sources.json",
+        serde_json::json!({
+            "sources": {
+                "site": { "format": "form", "event": "form.submitted" },
+                "bulk": { "format": "batch" }
+            }
+        })
+        .to_string(),
+    )
+    .unwrap();
+    env::set_var("SOURCES_FILE", "target/test-data/source_tests/sources.json");
+
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/in/{source}", web::post().to(source_handler))
+            .route("/deliveries/{id}", web::get().to(delivery_status_handler)),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::post()
+        .uri("/in/site")
+        .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+        .set_payload("first_name=Jo&email=jo%40example.com")
+        .to_request();
+    let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["status"], "ok");
+    let req = actix_web::test::TestRequest::get()
+        .uri(&format!("/deliveries/{}", body["delivery_id"].as_str().unwrap()))
+        .to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "succeeded");
+
+    // The built-in sch source takes the usual envelope.
+    let req = actix_web::test::TestRequest::post()
+        .uri("/in/sch")
+        .set_json(serde_json::json!({ "event": "customer.created", "data": { "first_name": "Sam" } }))
+        .to_request();
+    let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["status"], "ok");
+
+    // One bad event in a batch doesn't stop the others.
+    let req = actix_web::test::TestRequest::post()
+        .uri("/in/bulk")
+        .set_json(serde_json::json!([
+            { "event": "customer.created", "data": { "first_name": "A" } },
+            { "event": "customer.created" },
+            { "event": "customer.created", "data": { "first_name": "C" } }
+        ]))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 207);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let statuses: Vec<u64> = body["results"].as_array().unwrap().iter().map(|r| r["status"].as_u64().unwrap()).collect();
+    assert_eq!(statuses, vec![202, 400, 202]);
+    // Batch events wait for the worker instead of being forwarded inline.
+    let queued = body["results"][2]["body"]["delivery_id"].as_str().unwrap();
+    assert_eq!(body["results"][2]["body"]["status"], "queued");
+    let req = actix_web::test::TestRequest::get().uri(&format!("/deliveries/{}", queued)).to_request();
+    let status: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "queued");
+    queue::complete(queued);
+    queue::complete(body["results"][0]["body"]["delivery_id"].as_str().unwrap());
+
+    env::set_var("SOURCE_MAX_BATCH", "1");
+    let req = actix_web::test::TestRequest::post()
+        .uri("/in/bulk")
+        .set_json(serde_json::json!([
+            { "event": "customer.created", "data": { "first_name": "A" } },
+            { "event": "customer.created", "data": { "first_name": "B" } }
+        ]))
+        .to_request();
+    assert_eq!(actix_web::test::call_service(&app, req).await.status(), 413);
+    env::remove_var("SOURCE_MAX_BATCH");
+
+    let req = actix_web::test::TestRequest::post().uri("/in/nope").set_payload("{}").to_request();
+    assert_eq!(actix_web::test::call_service(&app, req).await.status(), 404);
+
+    let req = actix_web::test::TestRequest::post().uri("/in/sch").set_payload("not json").to_request();
+    assert_eq!(actix_web::test::call_service(&app, req).await.status(), 400);
+}
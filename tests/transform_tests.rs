@@ -0,0 +1,88 @@
+use sch2jn::field_map;
+use sch2jn::handlers::Payload;
+use sch2jn::transform::{Transform, TransformSpec};
+use std::collections::HashMap;
+use serde_json::{json, Value};
+
+fn run(transform: Value, value: Value) -> Result<Option<Value>, String> {
+    let spec: TransformSpec = serde_json::from_value(transform).unwrap();
+    spec.resolve()?.apply(Some(value), &|_| None)
+}
+
+#[test]
+fn test_text_transforms() {
+    assert_eq!(run(json!("trim"), json!("  Jane ")), Ok(Some(json!("Jane"))));
+    assert_eq!(run(json!("uppercase"), json!("fl")), Ok(Some(json!("FL"))));
+    assert_eq!(run(json!("titlecase"), json!("jANE roofer")), Ok(Some(json!("Jane Roofer"))));
+    assert_eq!(run(json!("first_name"), json!("Jane Q Roofer")), Ok(Some(json!("Jane"))));
+    assert_eq!(run(json!({ "fn": "split_name", "part": "last" }), json!("Roofer, Jane")), Ok(Some(json!("Roofer"))));
+    assert_eq!(run(json!("lowercase"), json!(["A", "B"])), Ok(Some(json!(["a", "b"]))));
+    assert!(run(json!("no_such_transform"), json!("x")).is_err());
+}
+
+#[test]
+fn test_concat_reads_other_paths() {
+    let concat = Transform::Concat { paths: vec!["last".to_string()], separator: " ".to_string() };
+    let lookup = |path: &str| (path == "last").then(|| json!("Roofer"));
+    assert_eq!(concat.apply(Some(json!("Jane")), &lookup), Ok(Some(json!("Jane Roofer"))));
+    assert_eq!(concat.apply(None, &lookup), Ok(Some(json!("Roofer"))));
+}
+
+#[test]
+fn test_numbers_and_phones() {
+    assert_eq!(run(json!("number"), json!("$1,250.50")), Ok(Some(json!(1250.5))));
+    assert_eq!(run(json!("integer"), json!("1,250")), Ok(Some(json!(1250))));
+    assert!(run(json!("integer"), json!("12.5")).is_err());
+    assert!(run(json!("number"), json!("twelve")).is_err());
+
+    assert_eq!(run(json!("phone_e164"), json!("727.555.0100")), Ok(Some(json!("+17275550100"))));
+    assert_eq!(run(json!("phone_e164"), json!("1 (727) 555-0100")), Ok(Some(json!("+17275550100"))));
+    assert_eq!(run(json!("phone_e164"), json!("+44 20 7946 0958")), Ok(Some(json!("+442079460958"))));
+    assert!(run(json!("phone_e164"), json!("555-0100")).is_err());
+}
+
+#[test]
+fn test_dates_and_lookups() {
+    let to_unix = json!({ "fn": "date", "tz": "America/New_York" });
+    assert_eq!(run(to_unix, json!("2024-03-01 09:00:00")), Ok(Some(json!(1709301600))));
+
+    let reformat = json!({ "fn": "date", "from": "%m/%d/%Y", "to": "%Y-%m-%d" });
+    assert_eq!(run(reformat, json!("03/01/2024")), Ok(Some(json!("2024-03-01"))));
+
+    let in_zone = json!({ "fn": "date", "to": "%Y-%m-%d %H:%M", "tz": "America/Chicago" });
+    assert_eq!(run(in_zone, json!("2024-03-01T15:00:00Z")), Ok(Some(json!("2024-03-01 09:00"))));
+    assert!(run(json!({ "fn": "date", "tz": "Mars/Base" }), json!("2024-03-01")).is_err());
+
+    let stages = json!({ "fn": "lookup", "table": { "Sold": "Job Sold", "Lead": "Lead" } });
+    assert_eq!(run(stages.clone(), json!("sold")), Ok(Some(json!("Job Sold"))));
+    assert!(run(stages, json!("Lost")).is_err());
+    let with_default = json!({ "fn": "lookup", "table": { "Sold": "Job Sold" }, "default": "Lead" });
+    assert_eq!(run(with_default, json!("Lost")), Ok(Some(json!("Lead"))));
+}
+
+#[test]
+fn test_bad_date_formats_are_rejected_at_load() {
+    let bad_to = json!({ "fn": "date", "to": "%Q" });
+    let spec: TransformSpec = serde_json::from_value(bad_to.clone()).unwrap();
+    assert!(spec.resolve().unwrap().validate().is_err());
+    // Even unvalidated, applying it fails instead of panicking.
+    assert!(run(bad_to, json!("2024-03-01")).is_err());
+    let bad_from: TransformSpec = serde_json::from_value(json!({ "fn": "date", "from": "%Y-%Q" })).unwrap();
+    assert!(bad_from.resolve().unwrap().validate().is_err());
+
+    let _ = std::fs::create_dir_all("logs");
+    std::fs::create_dir_all("target/test-data/transform_tests").unwrap();
+    let path = "target/test-data/transform_tests/mappings.json";
+    std::fs::write(path, json!({
+        "mappings": [{ "event": "project.*", "fields": { "date_start": { "path": "start", "transforms": [{ "fn": "date", "to": "%Q" }] } } }]
+    }).to_string()).unwrap();
+    std::env::set_var("MAPPINGS_FILE", path);
+    let error = field_map::reload().unwrap_err();
+    assert!(error.contains("%Q"), "{}", error);
+
+    // The handler never sees the bad mapping, so a matching payload goes through untouched.
+    let mut extra = HashMap::new();
+    extra.insert("event".to_string(), json!("project.created"));
+    let payload = Payload { data: Some(json!({ "start": "2024-03-01" })), _extra: extra };
+    assert_eq!(field_map::apply(&payload), Ok(json!({ "start": "2024-03-01" })));
+}
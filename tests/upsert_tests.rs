@@ -0,0 +1,127 @@
+use actix_web::{web, App, HttpResponse, HttpServer};
+use sch2jn::contacts::{match_keys, MatchKey};
+use sch2jn::handlers::{post_handler, Payload};
+use sch2jn::{http_client, idmap};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{create_dir_all, remove_dir_all};
+use std::sync::Mutex;
+
+// Tests that set environment variables take this so they don't see each
+// other's settings.
+static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+// Calls the mock JobNimbus received, in order.
+static CALLS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+async fn mock_search(query: web::Query<HashMap<String, String>>) -> HttpResponse {
+    let filter = &query["filter"];
+    CALLS.lock().unwrap().push(format!("GET {}", filter));
+    let results = if filter.contains("known@example.com") {
+        serde_json::json!([{ "jnid": "jn-existing" }])
+    } else {
+        serde_json::json!([])
+    };
+    HttpResponse::Ok().json(serde_json::json!({ "count": results.as_array().unwrap().len(), "results": results }))
+}
+
+async fn mock_update(jnid: web::Path<String>) -> HttpResponse {
+    CALLS.lock().unwrap().push(format!("PUT {}", jnid));
+    HttpResponse::Ok().json(serde_json::json!({ "jnid": jnid.into_inner() }))
+}
+
+async fn mock_create() -> HttpResponse {
+    CALLS.lock().unwrap().push("POST".to_string());
+    HttpResponse::Created().json(serde_json::json!({ "jnid": "jn-new" }))
+}
+
+#[test]
+fn test_match_keys_follow_configured_precedence() {
+    let _env = ENV_LOCK.blocking_lock();
+    env::set_var("CONTACT_MATCH_KEYS", "phone, email,bogus,phone");
+    assert_eq!(match_keys(), vec![MatchKey::Phone, MatchKey::Email]);
+    env::remove_var("CONTACT_MATCH_KEYS");
+    assert_eq!(match_keys(), vec![MatchKey::ExternalId, MatchKey::Email, MatchKey::Phone]);
+}
+
+#[test]
+fn test_sch_record_id_reads_configured_field() {
+    let _env = ENV_LOCK.blocking_lock();
+    let payload = Payload {
+        data: Some(serde_json::json!({ "id": 42, "customer": { "uuid": "abc-123" } })),
+        _extra: HashMap::new(),
+    };
+    env::set_var("SCH_ID_FIELD", "customer.uuid");
+    assert_eq!(idmap::sch_record_id(&payload).as_deref(), Some("abc-123"));
+    env::remove_var("SCH_ID_FIELD");
+    assert_eq!(idmap::sch_record_id(&payload).as_deref(), Some("42"));
+}
+
+
+#[actix_web::test]
+async fn test_upsert_updates_matches_and_creates_the_rest() {
+    let _env = ENV_LOCK.lock().await;
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/upsert_tests");
+    let _ = remove_dir_all("target/test-data/upsert_tests/idmap");
+    env::set_var("JOB_NIMBUS_API_KEY", "dummy");
+    env::set_var("JOBNIMBUS_RATE_LIMIT", "0");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+    env::set_var("MAPPINGS_FILE", "target/test-data/upsert_tests/no-mappings.json");
+    env::set_var("UPSERT_MODE", "true");
+    env::remove_var("TEST_MODE");
+
+    let mock = HttpServer::new(|| {
+        App::new()
+            .route("/api/contacts", web::get().to(mock_search))
+            .route("/api/contacts", web::post().to(mock_create))
+            .route("/api/contacts/{jnid}", web::put().to(mock_update))
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let addr = mock.addrs()[0];
+    actix_web::rt::spawn(mock.run());
+    env::set_var("JOBNIMBUS_BASE_URL", format!("http://{}", addr));
+    env::set_var("JOBNIMBUS_API_PREFIX", "api");
+
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler)),
+    )
+    .await;
+    let post = |data: serde_json::Value| {
+        let req = actix_web::test::TestRequest::post()
+            .set_json(serde_json::json!({ "event": "customer.updated", "data": data }))
+            .to_request();
+        actix_web::test::call_and_read_body_json::<_, _, serde_json::Value>(&app, req)
+    };
+    let take_calls = || std::mem::take(&mut *CALLS.lock().unwrap());
+
+    // A contact found by email is updated in place and its jnid remembered.
+    let body = post(serde_json::json!({ "id": "sch-1", "email": "known@example.com" })).await;
+    assert_eq!(body["status"], "delivered");
+    let calls = take_calls();
+    assert_eq!(calls.len(), 2, "{:?}", calls);
+    assert!(calls[0].starts_with("GET ") && calls[0].contains("known@example.com"), "{:?}", calls);
+    assert_eq!(calls[1], "PUT jn-existing");
+    assert_eq!(idmap::lookup_jnid("sch-1").as_deref(), Some("jn-existing"));
+
+    // No match by email or phone creates a new contact.
+    let body = post(serde_json::json!({ "id": "sch-2", "email": "new@example.com", "mobile_phone": "555-0100" })).await;
+    assert_eq!(body["status"], "delivered");
+    let calls = take_calls();
+    assert_eq!(calls.len(), 3, "{:?}", calls);
+    assert!(calls[0].contains("new@example.com"), "{:?}", calls);
+    assert!(calls[1].contains("555-0100"), "{:?}", calls);
+    assert_eq!(calls[2], "POST");
+    assert_eq!(idmap::lookup_jnid("sch-2").as_deref(), Some("jn-new"));
+
+    // Once mapped, the record is updated by its id without searching.
+    let body = post(serde_json::json!({ "id": "sch-2", "email": "changed@example.com" })).await;
+    assert_eq!(body["status"], "delivered");
+    assert_eq!(take_calls(), vec!["PUT jn-new".to_string()]);
+
+    env::remove_var("UPSERT_MODE");
+}
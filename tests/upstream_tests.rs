@@ -0,0 +1,87 @@
+use actix_web::{web, App, HttpResponse, HttpServer};
+use sch2jn::handlers::{discard_dead_letter_handler, post_handler};
+use sch2jn::http_client;
+use sch2jn::jobnimbus::api_base;
+use std::env;
+use std::fs::create_dir_all;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static UNAUTHORIZED: AtomicBool = AtomicBool::new(false);
+
+async fn mock_contacts(body: String) -> HttpResponse {
+    if UNAUTHORIZED.load(Ordering::SeqCst) {
+        return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "bad key" }));
+    }
+    let contact: serde_json::Value = serde_json::from_str(&body).unwrap();
+    HttpResponse::Created().json(serde_json::json!({ "jnid": "jn-mock-1", "first_name": contact["first_name"] }))
+}
+
+#[actix_web::test]
+async fn test_forwards_to_configured_base_url() {
+    let _ = create_dir_all("logs");
+    env::set_var("DATA_DIR", "target/test-data/upstream_tests");
+    env::set_var("JOB_NIMBUS_API_KEY", "dummy");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+    env::remove_var("TEST_MODE");
+
+    env::set_var("JOBNIMBUS_BASE_URL", "https://sandbox.example.com/");
+    env::set_var("JOBNIMBUS_API_PREFIX", "/v2/");
+    assert_eq!(api_base(), "https://sandbox.example.com/v2");
+    env::set_var("JOBNIMBUS_API_PREFIX", "");
+    assert_eq!(api_base(), "https://sandbox.example.com");
+
+    let mock = HttpServer::new(|| App::new().route("/mock-api/contacts", web::post().to(mock_contacts)))
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+    let addr = mock.addrs()[0];
+    actix_web::rt::spawn(mock.run());
+    env::set_var("JOBNIMBUS_BASE_URL", format!("http://{}", addr));
+    env::set_var("JOBNIMBUS_API_PREFIX", "mock-api");
+
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(web::Data::new(http_client::build().unwrap()))
+            .route("/", web::post().to(post_handler))
+            .route("/dead_letters/{id}", web::delete().to(discard_dead_letter_handler)),
+    )
+    .await;
+    let req = actix_web::test::TestRequest::post()
+        .set_json(serde_json::json!({ "data": { "first_name": "Mocked" } }))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "delivered");
+    assert_eq!(body["upstream_status"], 201);
+    assert_eq!(body["upstream_body"]["jnid"], "jn-mock-1");
+    assert_eq!(body["upstream_body"]["first_name"], "Mocked");
+
+    // A failed delivery is in the dead letters, so the sender's retry gets the
+    // same answer instead of a second copy, until the dead letter is discarded.
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "3600");
+    UNAUTHORIZED.store(true, Ordering::SeqCst);
+    let payload = serde_json::json!({ "data": { "first_name": "Retried", "nonce": chrono::Local::now().to_rfc3339() } });
+    let req = actix_web::test::TestRequest::post().set_json(&payload).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 502);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let parked = body["delivery_id"].as_str().unwrap().to_string();
+
+    UNAUTHORIZED.store(false, Ordering::SeqCst);
+    let req = actix_web::test::TestRequest::post().set_json(&payload).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 502);
+    assert_eq!(resp.headers().get("Idempotent-Replayed").unwrap(), "true");
+
+    let req = actix_web::test::TestRequest::delete().uri(&format!("/dead_letters/{}", parked)).to_request();
+    assert_eq!(actix_web::test::call_service(&app, req).await.status(), 200);
+    let req = actix_web::test::TestRequest::post().set_json(&payload).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("Idempotent-Replayed").is_none());
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["status"], "delivered");
+    env::set_var("IDEMPOTENCY_WINDOW_SECS", "0");
+}